@@ -2,33 +2,458 @@ use std::env;
 use std::error::Error;
 use std::net::SocketAddr;
 
-use tokio::time::{sleep, Duration};
-use tokio_modbus::client::tcp;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tokio_modbus::client::{tcp, Context};
 use tokio_modbus::prelude::*;
 
+use modbus_tcp_server_lib::word_order::WordOrder;
+
 const IO_COUNT: usize = 32;
 const STEP_DELAY_MS: u64 = 200;
+const SOAK_SPAN: u64 = 256;
+const SOAK_BLOCK: u16 = 8;
+
+enum Mode {
+    Io,
+    Float(u16, u16, WordOrder),
+    Soak(SoakOptions),
+    Probe(Duration),
+    Hold(Duration),
+    Stall(Duration),
+    Churn(ChurnOptions),
+}
+
+#[derive(Clone, Copy)]
+struct ChurnOptions {
+    count: u32,
+    interval: Duration,
+    wait: Duration,
+}
+
+impl ChurnOptions {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut options = ChurnOptions {
+            count: 10,
+            interval: Duration::from_millis(100),
+            wait: Duration::from_millis(500),
+        };
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let value = iter
+                .next()
+                .ok_or_else(|| format!("Missing value for {flag}"))?;
+            match flag.as_str() {
+                "--count" => options.count = value.parse()?,
+                "--interval" => options.interval = Duration::from_millis(value.parse()?),
+                "--wait" => options.wait = Duration::from_millis(value.parse()?),
+                other => return Err(format!("Unknown flag: {other}").into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SoakOptions {
+    connections: usize,
+    duration: Duration,
+    rate: u32,
+}
+
+impl SoakOptions {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut options = SoakOptions {
+            connections: 4,
+            duration: Duration::from_secs(10),
+            rate: 50,
+        };
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let value = iter
+                .next()
+                .ok_or_else(|| format!("Missing value for {flag}"))?;
+            match flag.as_str() {
+                "--connections" => options.connections = value.parse()?,
+                "--duration" => options.duration = Duration::from_secs(value.parse()?),
+                "--rate" => options.rate = value.parse()?,
+                other => return Err(format!("Unknown flag: {other}").into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+struct SoakStats {
+    requests: u64,
+    exceptions: u64,
+    errors: u64,
+}
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
+    let program = args.first().map(String::as_str).unwrap_or("modbus_client");
     if args.len() < 3 {
-        eprintln!(
-            "Usage: {} <ip> <port> [unit_id]\nExample: {} 127.0.0.1 502 1",
-            args.get(0).map(String::as_str).unwrap_or("modbus_client"),
-            args.get(0).map(String::as_str).unwrap_or("modbus_client")
-        );
+        print_usage(program);
         return Ok(());
     }
 
     let ip = &args[1];
     let port: u16 = args[2].parse()?;
     let unit_id: u8 = if args.len() > 3 { args[3].parse()? } else { 1 };
+    let command = args.get(4).map(String::as_str).unwrap_or("io");
     let socket_addr: SocketAddr = format!("{ip}:{port}").parse()?;
 
+    let mode = match command {
+        "io" => Mode::Io,
+        "soak" => Mode::Soak(SoakOptions::parse(&args[5..])?),
+        "probe" => {
+            let timeout_ms = match args.get(5) {
+                Some(value) => value.parse()?,
+                None => 200,
+            };
+            Mode::Probe(Duration::from_millis(timeout_ms))
+        }
+        "hold" => {
+            let secs = match args.get(5) {
+                Some(value) => value.parse()?,
+                None => 60,
+            };
+            Mode::Hold(Duration::from_secs(secs))
+        }
+        "stall" => {
+            let secs = match args.get(5) {
+                Some(value) => value.parse()?,
+                None => 60,
+            };
+            Mode::Stall(Duration::from_secs(secs))
+        }
+        "churn" => Mode::Churn(ChurnOptions::parse(&args[5..])?),
+        "float" => {
+            let (Some(address), Some(count)) = (args.get(5), args.get(6)) else {
+                print_usage(program);
+                return Ok(());
+            };
+            let order = args.get(7).map(String::as_str).unwrap_or("abcd");
+            let Ok(order) = order.parse::<WordOrder>() else {
+                eprintln!("Unknown word order: {order} (expected abcd, cdab, badc or dcba)");
+                return Ok(());
+            };
+            Mode::Float(address.parse()?, count.parse()?, order)
+        }
+        other => {
+            eprintln!("Unknown command: {other}");
+            print_usage(program);
+            return Ok(());
+        }
+    };
+
+    match mode {
+        Mode::Soak(options) => {
+            run_soak(socket_addr, unit_id, options).await;
+            return Ok(());
+        }
+        Mode::Hold(duration) => return hold_idle(socket_addr, duration).await,
+        Mode::Stall(duration) => return hold_stalled(socket_addr, unit_id, duration).await,
+        Mode::Churn(options) => {
+            run_churn(socket_addr, unit_id, options).await;
+            return Ok(());
+        }
+        _ => {}
+    }
+
     println!("Connecting to {socket_addr} (unit id {unit_id})...");
     let mut ctx = tcp::connect_slave(socket_addr, Slave(unit_id)).await?;
 
+    match mode {
+        Mode::Float(address, count, order) => read_floats(&mut ctx, address, count, order).await,
+        Mode::Probe(wait) => probe_units(&mut ctx, wait).await,
+        _ => run_io_loop(&mut ctx).await,
+    }
+}
+
+fn print_usage(program: &str) {
+    eprintln!(
+        "Usage: {program} <ip> <port> [unit_id] [command]\n\
+         Commands:\n  \
+           io                                   toggle coils and watch discrete inputs (default)\n  \
+           float <address> <count> [word_order] read <count> Float32 values from holding registers\n  \
+           soak [--connections N] [--duration SECS] [--rate REQ_PER_SEC]\n                                       \
+           run randomized reads/writes on N connections and report throughput\n  \
+           probe [timeout_ms]                   read HR[0] from unit ids 1..=247 and list responders\n  \
+           hold [secs]                          open one connection, send nothing and report when\n                                       \
+           the server closes it (default 60s)\n  \
+           stall [secs]                         like hold, after sending the first 9 of the 12\n                                       \
+           bytes of a read request\n  \
+           churn [--count N] [--interval MS] [--wait MS]\n                                       \
+           open, read HR[0] once and close N times, reporting accepted or refused\n\
+         Word orders: abcd (default), cdab, badc, dcba\n\
+         Example: {program} 127.0.0.1 502 1 float 0 4 cdab\n\
+         Example: {program} 127.0.0.1 502 1 soak --connections 16 --duration 30 --rate 100\n\
+         Example: {program} 127.0.0.1 502 1 probe 500\n\
+         Example: {program} 127.0.0.1 502 1 churn --count 50 --interval 20"
+    );
+}
+
+async fn read_floats(
+    ctx: &mut Context,
+    address: u16,
+    count: u16,
+    order: WordOrder,
+) -> Result<(), Box<dyn Error>> {
+    let Some(quantity) = count.checked_mul(2) else {
+        return Err(format!("Too many values: {count} (at most {})", u16::MAX / 2).into());
+    };
+    let words = ctx.read_holding_registers(address, quantity).await??;
+    for (index, pair) in words.chunks_exact(2).enumerate() {
+        let register = address as usize + index * 2;
+        println!(
+            "HR[{}..={}] {:#06x} {:#06x} -> {}",
+            register,
+            register + 1,
+            pair[0],
+            pair[1],
+            order.decode_f32(pair[0], pair[1])
+        );
+    }
+    Ok(())
+}
+
+/// Units that answer at all, including with an exception, are reported as responding; units
+/// the server ignores time out after `wait`.
+async fn probe_units(ctx: &mut Context, wait: Duration) -> Result<(), Box<dyn Error>> {
+    let mut responders = Vec::new();
+    for unit in 1..=247u8 {
+        ctx.set_slave(Slave(unit));
+        let started = Instant::now();
+        let outcome = match timeout(wait, ctx.read_holding_registers(0, 1)).await {
+            Ok(Ok(Ok(_))) => "ok".to_string(),
+            Ok(Ok(Err(exception))) => format!("exception {exception:?}"),
+            Ok(Err(err)) => return Err(err.into()),
+            Err(_) => continue,
+        };
+        let elapsed = started.elapsed();
+        println!("unit {unit:>3}: {outcome} in {:.1} ms", elapsed.as_secs_f64() * 1000.0);
+        responders.push(unit);
+    }
+    println!("{} of 247 unit ids responded: {responders:?}", responders.len());
+    Ok(())
+}
+
+/// Holds a connection open without sending anything, which is what an idle timeout or a
+/// connection limit applies to. A closed connection is noticed as soon as the server drops it.
+async fn hold_idle(socket_addr: SocketAddr, duration: Duration) -> Result<(), Box<dyn Error>> {
+    println!("Holding an idle connection to {socket_addr} for {}s...", duration.as_secs());
+    let stream = TcpStream::connect(socket_addr).await?;
+    report_close(&stream, duration).await;
+    Ok(())
+}
+
+/// Sends a truncated FC03 request, header and function code plus one byte of the address, and
+/// then nothing, which is what a read timeout applies to.
+async fn hold_stalled(
+    socket_addr: SocketAddr,
+    unit_id: u8,
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    println!(
+        "Holding a connection to {socket_addr} mid-request for {}s...",
+        duration.as_secs()
+    );
+    let stream = TcpStream::connect(socket_addr).await?;
+    let partial = [0x00, 0x01, 0x00, 0x00, 0x00, 0x06, unit_id, 0x03, 0x00];
+    stream.writable().await?;
+    stream.try_write(&partial)?;
+    report_close(&stream, duration).await;
+    Ok(())
+}
+
+async fn report_close(stream: &TcpStream, duration: Duration) {
+    let started = Instant::now();
+    match timeout(duration, wait_closed(stream)).await {
+        Ok(Ok(())) => println!(
+            "Closed by the server after {:.1}s",
+            started.elapsed().as_secs_f64()
+        ),
+        Ok(Err(err)) => println!(
+            "Connection failed after {:.1}s: {err}",
+            started.elapsed().as_secs_f64()
+        ),
+        Err(_) => println!("Still open after {}s, closing", duration.as_secs()),
+    }
+}
+
+async fn wait_closed(stream: &TcpStream) -> std::io::Result<()> {
+    let mut buf = [0u8; 256];
+    loop {
+        stream.readable().await?;
+        match stream.try_read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Opens and closes connections back to back. Each one reads HR[0] once: any reply, an
+/// exception included, means the server accepted it; a connection the server drops is
+/// refused, and one still unanswered after `wait` was most likely queued behind the limit.
+async fn run_churn(socket_addr: SocketAddr, unit_id: u8, options: ChurnOptions) {
+    println!(
+        "Opening {} connections to {socket_addr} (unit id {unit_id}), {} ms apart...",
+        options.count,
+        options.interval.as_millis()
+    );
+    let (mut accepted, mut refused, mut unanswered) = (0u32, 0u32, 0u32);
+    for index in 0..options.count {
+        let outcome = match tcp::connect_slave(socket_addr, Slave(unit_id)).await {
+            Ok(mut ctx) => match timeout(options.wait, ctx.read_holding_registers(0, 1)).await {
+                Ok(Ok(Ok(_))) => {
+                    accepted += 1;
+                    "accepted".to_string()
+                }
+                Ok(Ok(Err(exception))) => {
+                    accepted += 1;
+                    format!("accepted, exception {exception:?}")
+                }
+                Ok(Err(err)) => {
+                    refused += 1;
+                    format!("refused: {err}")
+                }
+                Err(_) => {
+                    unanswered += 1;
+                    format!("no reply within {} ms", options.wait.as_millis())
+                }
+            },
+            Err(err) => {
+                refused += 1;
+                format!("refused at connect: {err}")
+            }
+        };
+        println!("[conn {index}] {outcome}");
+        if !options.interval.is_zero() {
+            sleep(options.interval).await;
+        }
+    }
+    println!("{accepted} accepted, {refused} refused, {unanswered} unanswered");
+}
+
+async fn run_soak(socket_addr: SocketAddr, unit_id: u8, options: SoakOptions) {
+    println!(
+        "Soaking {socket_addr} (unit id {unit_id}) with {} connections for {}s at {} req/s each...",
+        options.connections,
+        options.duration.as_secs(),
+        options.rate
+    );
+    let started = Instant::now();
+    let workers: Vec<_> = (0..options.connections)
+        .map(|index| tokio::spawn(soak_worker(socket_addr, unit_id, index as u64, options)))
+        .collect();
+
+    let mut total = SoakStats::default();
+    for worker in workers {
+        match worker.await {
+            Ok(stats) => {
+                total.requests += stats.requests;
+                total.exceptions += stats.exceptions;
+                total.errors += stats.errors;
+            }
+            Err(err) => {
+                eprintln!("Worker panicked: {err}");
+                total.errors += 1;
+            }
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Completed {} requests in {:.2}s ({:.1} req/s), {} exceptions, {} errors",
+        total.requests,
+        elapsed,
+        total.requests as f64 / elapsed.max(f64::EPSILON),
+        total.exceptions,
+        total.errors
+    );
+}
+
+async fn soak_worker(
+    socket_addr: SocketAddr,
+    unit_id: u8,
+    index: u64,
+    options: SoakOptions,
+) -> SoakStats {
+    let mut stats = SoakStats::default();
+    let mut ctx = match tcp::connect_slave(socket_addr, Slave(unit_id)).await {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            eprintln!("[conn {index}] connect failed: {err}");
+            stats.errors += 1;
+            return stats;
+        }
+    };
+
+    let mut rng = XorShift(0x2545_F491_4F6C_DD1D ^ (index + 1));
+    let interval = if options.rate == 0 {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(1.0 / options.rate as f64)
+    };
+    let deadline = Instant::now() + options.duration;
+
+    while Instant::now() < deadline {
+        let address = (rng.next_u64() % SOAK_SPAN) as u16;
+        let result = match rng.next_u64() % 4 {
+            0 => ctx
+                .read_holding_registers(address, SOAK_BLOCK)
+                .await
+                .map(|response| response.map(|_| ())),
+            1 => ctx
+                .read_coils(address, SOAK_BLOCK)
+                .await
+                .map(|response| response.map(|_| ())),
+            2 => ctx
+                .write_single_register(address, rng.next_u64() as u16)
+                .await,
+            _ => ctx
+                .write_single_coil(address, rng.next_u64() % 2 == 0)
+                .await,
+        };
+        match result {
+            Ok(Ok(())) => stats.requests += 1,
+            Ok(Err(exception)) => {
+                stats.requests += 1;
+                stats.exceptions += 1;
+                eprintln!("[conn {index}] exception at {address}: {exception}");
+            }
+            Err(err) => {
+                stats.errors += 1;
+                eprintln!("[conn {index}] transport error: {err}");
+                break;
+            }
+        }
+        if !interval.is_zero() {
+            sleep(interval).await;
+        }
+    }
+
+    stats
+}
+
+async fn run_io_loop(ctx: &mut Context) -> Result<(), Box<dyn Error>> {
     let mut output_index = 0usize;
     let mut last_inputs = vec![false; IO_COUNT];
 
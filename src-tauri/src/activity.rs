@@ -0,0 +1,64 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sink::UpdateSink;
+use crate::unix_millis;
+
+/// Verbosity of the `modbus://log` stream; each level includes the ones before it.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+}
+
+#[derive(Clone, Serialize)]
+struct LogEntry {
+    level: LogLevel,
+    timestamp_ms: u64,
+    message: String,
+    peer: Option<String>,
+}
+
+/// Human-readable lifecycle entries for the in-app log viewer.
+#[derive(Clone)]
+pub struct ActivityLog {
+    sink: Arc<dyn UpdateSink>,
+    level: LogLevel,
+}
+
+impl ActivityLog {
+    pub fn new(sink: Arc<dyn UpdateSink>, level: LogLevel) -> Self {
+        Self { sink, level }
+    }
+
+    pub fn info(&self, message: impl Into<String>, peer: Option<SocketAddr>) {
+        self.emit(LogLevel::Info, message.into(), peer);
+    }
+
+    pub fn warn(&self, message: impl Into<String>, peer: Option<SocketAddr>) {
+        self.emit(LogLevel::Warn, message.into(), peer);
+    }
+
+    pub fn error(&self, message: impl Into<String>, peer: Option<SocketAddr>) {
+        self.emit(LogLevel::Error, message.into(), peer);
+    }
+
+    fn emit(&self, level: LogLevel, message: String, peer: Option<SocketAddr>) {
+        if level > self.level {
+            return;
+        }
+        let entry = LogEntry {
+            level,
+            timestamp_ms: unix_millis(),
+            message,
+            peer: peer.map(|peer| peer.to_string()),
+        };
+        self.sink.send("modbus://log", entry);
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use crate::modbus::MAX_AREA_SIZE;
+
+/// Fixed-width little-endian encoding of a value in a mapped file.
+pub trait Cell: Copy {
+    const SIZE: usize;
+
+    fn decode(bytes: &[u8]) -> Self;
+
+    fn encode(self, bytes: &mut [u8]);
+}
+
+impl Cell for bool {
+    const SIZE: usize = 1;
+
+    fn decode(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+
+    fn encode(self, bytes: &mut [u8]) {
+        bytes[0] = self as u8;
+    }
+}
+
+impl Cell for u16 {
+    const SIZE: usize = 2;
+
+    fn decode(bytes: &[u8]) -> Self {
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+
+    fn encode(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Values of one data area, together with which addresses have been written. `Dense` keeps
+/// every address in a vector; `Compact` keeps only written addresses in a map and reads the
+/// rest as `fill`, trading a hash lookup per access for memory on large, sparsely used areas.
+/// `Mapped` keeps the values in a shared file mapping, so they persist and other processes
+/// can read them; which addresses were written is not persisted.
+#[derive(Debug)]
+pub enum Area<T> {
+    Dense {
+        values: Vec<T>,
+        written: Vec<bool>,
+    },
+    Compact {
+        len: usize,
+        fill: T,
+        values: HashMap<u16, T>,
+    },
+    Mapped {
+        file: File,
+        map: MmapMut,
+        len: usize,
+        written: Vec<bool>,
+    },
+}
+
+/// A file opened and mapped by `Area::open_mapping` that no area uses yet.
+#[derive(Debug)]
+pub struct Mapping {
+    file: File,
+    map: MmapMut,
+    len: usize,
+    /// The file was empty and is filled from the area once mapped.
+    fresh: bool,
+}
+
+impl Mapping {
+    /// Gives up a mapping that was never passed to `Area::map`. A file that was empty is
+    /// truncated again, so the next attempt still fills it from the store.
+    pub fn discard(self) {
+        if self.fresh {
+            drop(self.map);
+            let _ = self.file.set_len(0);
+        }
+    }
+}
+
+/// Copies of a mapped area, such as published snapshots and the frozen view, are dense.
+impl<T: Cell> Clone for Area<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Area::Dense { values, written } => Area::Dense {
+                values: values.clone(),
+                written: written.clone(),
+            },
+            Area::Compact { len, fill, values } => Area::Compact {
+                len: *len,
+                fill: *fill,
+                values: values.clone(),
+            },
+            Area::Mapped { written, .. } => Area::Dense {
+                values: self.to_vec(),
+                written: written.clone(),
+            },
+        }
+    }
+}
+
+impl<T: Cell> Area<T> {
+    pub fn new(len: usize, fill: T) -> Self {
+        Area::Dense {
+            values: vec![fill; len],
+            written: vec![false; len],
+        }
+    }
+
+    /// An area holding `values`, every one of them counting as written.
+    pub fn from_values(values: Vec<T>, compact: bool, fill: T) -> Self {
+        let len = values.len();
+        let mut area = Area::Dense {
+            values,
+            written: vec![true; len],
+        };
+        area.set_compact(compact, fill);
+        area
+    }
+
+    pub fn is_compact(&self) -> bool {
+        matches!(self, Area::Compact { .. })
+    }
+
+    /// Switches the backing, keeping values and written addresses. Unwritten addresses of a
+    /// compact area read as `fill`, which must be the value they hold in the dense one.
+    pub fn set_compact(&mut self, compact: bool, fill: T) {
+        if compact == self.is_compact() {
+            return;
+        }
+        *self = match self {
+            Area::Dense { .. } | Area::Mapped { .. } => Area::Compact {
+                len: self.len(),
+                fill,
+                values: (0..self.len())
+                    .filter(|index| self.is_written(*index, 1))
+                    .filter_map(|index| Some((index as u16, self.get(index)?)))
+                    .collect(),
+            },
+            Area::Compact { len, fill, values } => {
+                let mut dense = Area::new(*len, *fill);
+                for (&index, &value) in values.iter() {
+                    dense.write(index as usize, &[value]);
+                    dense.mark_written(index as usize, 1);
+                }
+                dense
+            }
+        };
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Area::Mapped { .. })
+    }
+
+    /// Opens and maps the file at `path` for this area, holding each address as `T::SIZE`
+    /// bytes, little-endian, at `address * T::SIZE`. A non-empty file keeps its contents,
+    /// which set the area's size; an empty one is sized for the area. The area itself is left
+    /// alone until the mapping is passed to `map`.
+    pub fn open_mapping(&self, path: &Path) -> io::Result<Mapping> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let existing = file.metadata()?.len() as usize / T::SIZE;
+        if existing > MAX_AREA_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} holds more than {MAX_AREA_SIZE} values", path.display()),
+            ));
+        }
+        let len = if existing > 0 { existing } else { self.len() };
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "An empty area cannot be mapped",
+            ));
+        }
+        file.set_len((len * T::SIZE) as u64)?;
+        // SAFETY: the mapping is only accessed through this area, under the store lock. Other
+        // processes may change the file underneath, which can only produce unexpected values,
+        // as every byte pattern decodes to a valid `T`.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(Mapping {
+            file,
+            map,
+            len,
+            fresh: existing == 0,
+        })
+    }
+
+    /// Moves the area into `mapping`. A file that was empty takes the area's values and
+    /// written addresses; otherwise the file's values replace them and all count as written.
+    pub fn map(&mut self, mapping: Mapping) {
+        let Mapping {
+            file,
+            map,
+            len,
+            fresh,
+        } = mapping;
+        let (written, values) = if fresh {
+            let written = (0..len).map(|index| self.is_written(index, 1));
+            (written.collect(), Some(self.to_vec()))
+        } else {
+            (vec![true; len], None)
+        };
+        *self = Area::Mapped {
+            file,
+            map,
+            len,
+            written,
+        };
+        if let Some(values) = values {
+            self.write(0, &values);
+        }
+    }
+
+    /// Moves a mapped area back onto the heap, leaving the file with its last values.
+    pub fn unmap(&mut self) {
+        if let Area::Mapped { map, .. } = self {
+            let _ = map.flush();
+            *self = self.clone();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Area::Dense { values, .. } => values.len(),
+            Area::Compact { len, .. } | Area::Mapped { len, .. } => *len,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<T> {
+        match self {
+            Area::Dense { values, .. } => values.get(index).copied(),
+            Area::Compact { len, fill, values } => {
+                (index < *len).then(|| values.get(&(index as u16)).copied().unwrap_or(*fill))
+            }
+            Area::Mapped { map, len, .. } => {
+                (index < *len).then(|| T::decode(&map[index * T::SIZE..(index + 1) * T::SIZE]))
+            }
+        }
+    }
+
+    /// Values of `start..end`, or `None` if the range is out of bounds.
+    pub fn slice(&self, start: usize, end: usize) -> Option<Vec<T>> {
+        if start > end || end > self.len() {
+            return None;
+        }
+        match self {
+            Area::Dense { values, .. } => Some(values[start..end].to_vec()),
+            _ => (start..end).map(|index| self.get(index)).collect(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.slice(0, self.len()).unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len()).filter_map(move |index| self.get(index))
+    }
+
+    /// Writes `data` from `start`, or nothing and returns `false` if it does not fit. In a
+    /// compact area this also marks the addresses written.
+    pub fn write(&mut self, start: usize, data: &[T]) -> bool {
+        let end = start + data.len();
+        if end > self.len() {
+            return false;
+        }
+        match self {
+            Area::Dense { values, .. } => values[start..end].copy_from_slice(data),
+            Area::Compact { values, .. } => {
+                for (offset, value) in data.iter().enumerate() {
+                    values.insert((start + offset) as u16, *value);
+                }
+            }
+            Area::Mapped { map, .. } => {
+                let bytes = &mut map[start * T::SIZE..end * T::SIZE];
+                for (chunk, value) in bytes.chunks_exact_mut(T::SIZE).zip(data) {
+                    value.encode(chunk);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn set(&mut self, index: usize, value: T) -> bool {
+        self.write(index, &[value])
+    }
+
+    pub fn mark_written(&mut self, start: usize, len: usize) {
+        let end = (start + len).min(self.len());
+        match self {
+            Area::Dense { written, .. } | Area::Mapped { written, .. } => {
+                if start < end {
+                    written[start..end].fill(true);
+                }
+            }
+            Area::Compact { fill, values, .. } => {
+                for index in start..end {
+                    values.entry(index as u16).or_insert(*fill);
+                }
+            }
+        }
+    }
+
+    pub fn is_written(&self, start: usize, len: usize) -> bool {
+        match self {
+            Area::Dense { written, .. } | Area::Mapped { written, .. } => written
+                .get(start..start + len)
+                .is_some_and(|written| written.iter().all(|value| *value)),
+            Area::Compact { len: size, values, .. } => {
+                start + len <= *size
+                    && (start..start + len).all(|index| values.contains_key(&(index as u16)))
+            }
+        }
+    }
+
+    /// Switches the value of every unwritten address to `fill`.
+    pub fn set_fill(&mut self, fill: T) {
+        match self {
+            Area::Dense { values, written } => {
+                for (slot, written) in values.iter_mut().zip(written.iter()) {
+                    if !written {
+                        *slot = fill;
+                    }
+                }
+            }
+            Area::Compact { fill: current, .. } => *current = fill,
+            Area::Mapped { map, written, .. } => {
+                for (chunk, written) in map.chunks_exact_mut(T::SIZE).zip(written.iter()) {
+                    if !written {
+                        fill.encode(chunk);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forgets every write, leaving all addresses at `fill`.
+    pub fn reset(&mut self, fill: T) {
+        match self {
+            Area::Dense { values, written } => {
+                values.fill(fill);
+                written.fill(false);
+            }
+            Area::Compact {
+                fill: current,
+                values,
+                ..
+            } => {
+                *current = fill;
+                values.clear();
+            }
+            Area::Mapped { map, written, .. } => {
+                for chunk in map.chunks_exact_mut(T::SIZE) {
+                    fill.encode(chunk);
+                }
+                written.fill(false);
+            }
+        }
+    }
+
+    /// Grows with unwritten addresses at `fill`, or drops the addresses past `len`. A compact
+    /// area grows with its own fill value; a mapped one resizes its file.
+    pub fn resize(&mut self, len: usize, fill: T) -> io::Result<()> {
+        match self {
+            Area::Dense { values, written } => {
+                values.resize(len, fill);
+                written.resize(len, false);
+            }
+            Area::Compact {
+                len: size, values, ..
+            } => {
+                *size = len;
+                values.retain(|index, _| (*index as usize) < len);
+            }
+            Area::Mapped {
+                file,
+                map,
+                len: size,
+                written,
+            } => {
+                if len == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "A mapped area cannot be empty",
+                    ));
+                }
+                map.flush()?;
+                file.set_len((len * T::SIZE) as u64)?;
+                // SAFETY: as in `open_mapping`; the old mapping is dropped on assignment.
+                *map = unsafe { MmapMut::map_mut(&*file)? };
+                let old = *size;
+                *size = len;
+                written.resize(len, false);
+                for chunk in map.chunks_exact_mut(T::SIZE).skip(old) {
+                    fill.encode(chunk);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the values and written addresses of `other`, keeping this area's backing.
+    pub fn restore(&mut self, other: &Area<T>, fill: T) -> io::Result<()> {
+        if !self.is_mapped() {
+            *self = other.clone();
+            return Ok(());
+        }
+        self.resize(other.len(), fill)?;
+        self.write(0, &other.to_vec());
+        if let Area::Mapped { written, .. } = self {
+            for (index, flag) in written.iter_mut().enumerate() {
+                *flag = other.is_written(index, 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces every value with `values`, all counting as written, keeping the backing.
+    pub fn replace(&mut self, values: Vec<T>, fill: T) -> io::Result<()> {
+        if !self.is_mapped() {
+            *self = Area::from_values(values, self.is_compact(), fill);
+            return Ok(());
+        }
+        self.resize(values.len(), fill)?;
+        self.write(0, &values);
+        self.mark_written(0, values.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    #[test]
+    fn open_mapping_leaves_the_area_alone_until_mapped() {
+        let dir = TempDir::new();
+        let path = dir.path().join("registers.u16le");
+        let mut area = Area::from_values(vec![1u16, 0x0203, 3], false, 0);
+        let mapping = area.open_mapping(&path).unwrap();
+        assert!(!area.is_mapped());
+        area.map(mapping);
+        assert!(area.is_mapped());
+        assert_eq!(area.to_vec(), vec![1, 0x0203, 3]);
+        area.unmap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes, vec![1, 0, 3, 2, 3, 0]);
+    }
+
+    #[test]
+    fn existing_file_sets_the_size_and_values() {
+        let dir = TempDir::new();
+        let path = dir.path().join("registers.u16le");
+        std::fs::write(&path, [7, 0, 8, 0]).unwrap();
+        let mut area = Area::new(10, 0u16);
+        let mapping = area.open_mapping(&path).unwrap();
+        area.map(mapping);
+        assert_eq!(area.to_vec(), vec![7, 8]);
+        assert!(area.is_written(0, 2));
+    }
+
+    #[test]
+    fn discarded_fresh_mapping_truncates_the_file() {
+        let dir = TempDir::new();
+        let path = dir.path().join("coils.bin");
+        let area = Area::new(8, false);
+        let mapping = area.open_mapping(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 8);
+        mapping.discard();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    }
+}
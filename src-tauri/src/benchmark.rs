@@ -0,0 +1,162 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::time::timeout;
+use tokio_modbus::client::{tcp, Reader, Writer};
+use tokio_modbus::slave::Slave;
+
+use crate::latency::{LatencyHistograms, LatencyStats};
+use crate::rng::SplitMix64;
+
+const BLOCK: u16 = 8;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+const READ_COILS: u8 = 0x01;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const MASK_WRITE_REGISTER: u8 = 0x16;
+
+pub struct Target {
+    pub addr: SocketAddr,
+    pub unit: u8,
+    pub coils: usize,
+    pub holding_registers: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct BenchmarkReport {
+    pub connections: usize,
+    pub elapsed_ms: u64,
+    pub requests: u64,
+    pub exceptions: u64,
+    pub errors: u64,
+    pub requests_per_sec: f64,
+    /// Round trip seen by the client, per function code.
+    pub latency: Vec<LatencyStats>,
+}
+
+#[derive(Default)]
+struct WorkerStats {
+    connected: bool,
+    requests: u64,
+    exceptions: u64,
+    errors: u64,
+}
+
+/// Address to connect to for a listener bound to `addr`: the loopback address of the same
+/// family when it is bound to every interface.
+pub fn loopback_target(addr: SocketAddr) -> SocketAddr {
+    if !addr.ip().is_unspecified() {
+        return addr;
+    }
+    let ip = match addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    };
+    SocketAddr::new(ip, addr.port())
+}
+
+/// Runs `concurrency` connections against `target` until `duration` has passed, each sending
+/// its next request as soon as the previous one is answered. Writes are FC22 with an all-ones
+/// AND mask and a zero OR mask, which go through the full write path but leave values as they
+/// were.
+pub async fn run(target: Target, duration: Duration, concurrency: usize) -> BenchmarkReport {
+    let target = Arc::new(target);
+    let latency = Arc::new(LatencyHistograms::default());
+    let started = Instant::now();
+    let deadline = started + duration;
+    let workers: Vec<_> = (0..concurrency)
+        .map(|index| tokio::spawn(worker(target.clone(), index as u64, deadline, latency.clone())))
+        .collect();
+
+    let mut total = WorkerStats::default();
+    let mut connections = 0;
+    for worker in workers {
+        match worker.await {
+            Ok(stats) => {
+                connections += usize::from(stats.connected);
+                total.requests += stats.requests;
+                total.exceptions += stats.exceptions;
+                total.errors += stats.errors;
+            }
+            Err(_) => total.errors += 1,
+        }
+    }
+
+    let elapsed = started.elapsed();
+    BenchmarkReport {
+        connections,
+        elapsed_ms: elapsed.as_millis() as u64,
+        requests: total.requests,
+        exceptions: total.exceptions,
+        errors: total.errors,
+        requests_per_sec: total.requests as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        latency: latency.stats(),
+    }
+}
+
+async fn worker(
+    target: Arc<Target>,
+    seed: u64,
+    deadline: Instant,
+    latency: Arc<LatencyHistograms>,
+) -> WorkerStats {
+    let mut stats = WorkerStats::default();
+    let mut ctx = match tcp::connect_slave(target.addr, Slave(target.unit)).await {
+        Ok(ctx) => ctx,
+        Err(_) => {
+            stats.errors += 1;
+            return stats;
+        }
+    };
+    stats.connected = true;
+
+    let mut rng = SplitMix64::new(seed);
+    while Instant::now() < deadline {
+        let coils = target.holding_registers == 0 || (rng.next_u64() % 3 == 0 && target.coils > 0);
+        let len = if coils {
+            target.coils
+        } else {
+            target.holding_registers
+        };
+        let qty = BLOCK.min(len as u16).max(1);
+        let addr = (rng.next_u64() % (len.saturating_sub(qty as usize) + 1) as u64) as u16;
+        let sent = Instant::now();
+        let (function_code, request) = match (coils, rng.next_u64() % 2) {
+            (true, _) => (READ_COILS, discard(ctx.read_coils(addr, qty)).await),
+            (false, 0) => (
+                READ_HOLDING_REGISTERS,
+                discard(ctx.read_holding_registers(addr, qty)).await,
+            ),
+            (false, _) => (
+                MASK_WRITE_REGISTER,
+                timeout(REQUEST_TIMEOUT, ctx.masked_write_register(addr, 0xFFFF, 0x0000)).await,
+            ),
+        };
+        match request {
+            Ok(Ok(Ok(()))) => {
+                stats.requests += 1;
+                latency.record(function_code, sent.elapsed());
+            }
+            Ok(Ok(Err(_))) => {
+                stats.requests += 1;
+                stats.exceptions += 1;
+                latency.record(function_code, sent.elapsed());
+            }
+            Ok(Err(_)) | Err(_) => {
+                stats.errors += 1;
+                break;
+            }
+        }
+    }
+    stats
+}
+
+async fn discard<T>(
+    request: impl std::future::Future<Output = tokio_modbus::Result<T>>,
+) -> Result<tokio_modbus::Result<()>, tokio::time::error::Elapsed> {
+    timeout(REQUEST_TIMEOUT, request)
+        .await
+        .map(|response| response.map(|response| response.map(drop)))
+}
+
@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::unix_millis;
+
+const CAPACITY: usize = 256;
+
+#[derive(Serialize, Clone)]
+pub struct CommandRecord {
+    pub timestamp_ms: u64,
+    pub command: &'static str,
+    pub args: String,
+}
+
+/// The most recent state-changing commands invoked by the frontend, oldest first, bounded to
+/// the last `CAPACITY`. Arguments are kept as a short summary, never as full value payloads.
+#[derive(Default)]
+pub struct CommandLog {
+    records: Mutex<VecDeque<CommandRecord>>,
+}
+
+impl CommandLog {
+    pub fn record(&self, command: &'static str, args: impl Into<String>) {
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() == CAPACITY {
+                records.pop_front();
+            }
+            records.push_back(CommandRecord {
+                timestamp_ms: unix_millis(),
+                command,
+                args: args.into(),
+            });
+        }
+    }
+
+    pub fn latest(&self, limit: usize) -> Vec<CommandRecord> {
+        self.records
+            .lock()
+            .map(|records| {
+                let skip = records.len().saturating_sub(limit);
+                records.iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut records) = self.records.lock() {
+            records.clear();
+        }
+    }
+}
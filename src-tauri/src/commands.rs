@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// Built-in actions a master can trigger by writing to a command register.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandAction {
+    Freeze,
+    FreezeRejectWrites,
+    Unfreeze,
+    ResetMetrics,
+    ResetStore,
+}
+
+/// Fires `action` when a master writes `value` to holding register `address`. The register
+/// is written as usual first; the action runs once the request's write lock is released.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommandRegister {
+    pub address: u16,
+    pub value: u16,
+    pub action: CommandAction,
+}
+
+pub fn matching_actions(
+    commands: &[CommandRegister],
+    offset: u16,
+    values: &[u16],
+) -> Vec<CommandAction> {
+    commands
+        .iter()
+        .filter(|command| {
+            command
+                .address
+                .checked_sub(offset)
+                .and_then(|index| values.get(index as usize))
+                == Some(&command.value)
+        })
+        .map(|command| command.action)
+        .collect()
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::metrics::TrafficCounters;
+use crate::transport::CoilPackingLog;
+
+pub struct ConnectionEntry {
+    pub id: u64,
+    pub peer: SocketAddr,
+    pub traffic: TrafficCounters,
+    pub coil_packing: Option<CoilPackingLog>,
+    requests: AtomicU64,
+    closing: AtomicBool,
+    units: Mutex<UnitStats>,
+}
+
+/// Request counts per tracked unit id, with untracked ids bucketed under `other`. Distinct
+/// ids are counted over every unit, tracked or not, so scan detection still sees them.
+#[derive(Default)]
+struct UnitStats {
+    counts: BTreeMap<u8, u64>,
+    other: u64,
+    seen: [u64; 4],
+    distinct: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub peer: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub requests: u64,
+    pub units: Vec<u8>,
+    pub other_unit_requests: u64,
+    pub primary_unit: Option<u8>,
+}
+
+impl ConnectionEntry {
+    pub fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            id: self.id,
+            peer: self.peer.to_string(),
+            bytes_in: self.traffic.bytes_in(),
+            bytes_out: self.traffic.bytes_out(),
+            requests: self.requests.load(Ordering::SeqCst),
+            units: self.units(),
+            other_unit_requests: self
+                .units
+                .lock()
+                .map(|units| units.other)
+                .unwrap_or_default(),
+            primary_unit: self.primary_unit(),
+        }
+    }
+
+    /// Records a request addressed to `unit`, under its own entry when `tracked` and in the
+    /// `other` bucket otherwise, and returns the number of distinct unit ids seen so far.
+    pub fn record_unit(&self, unit: u8, tracked: bool) -> usize {
+        let Ok(mut units) = self.units.lock() else {
+            return 0;
+        };
+        let (word, bit) = (unit as usize / 64, 1u64 << (unit % 64));
+        if units.seen[word] & bit == 0 {
+            units.seen[word] |= bit;
+            units.distinct += 1;
+        }
+        if tracked {
+            *units.counts.entry(unit).or_default() += 1;
+        } else {
+            units.other += 1;
+        }
+        units.distinct
+    }
+
+    pub fn units(&self) -> Vec<u8> {
+        self.units
+            .lock()
+            .map(|units| units.counts.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// The unit id this connection has addressed most often.
+    pub fn primary_unit(&self) -> Option<u8> {
+        let units = self.units.lock().ok()?;
+        units
+            .counts
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(unit, _)| *unit)
+    }
+
+    pub fn record_request(&self) -> u64 {
+        self.requests.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ConnectionRegistry {
+    next_id: Arc<AtomicU64>,
+    entries: Arc<Mutex<BTreeMap<u64, Arc<ConnectionEntry>>>>,
+}
+
+impl ConnectionRegistry {
+    pub fn register(
+        &self,
+        peer: SocketAddr,
+        coil_packing: Option<CoilPackingLog>,
+    ) -> Arc<ConnectionEntry> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let entry = Arc::new(ConnectionEntry {
+            id,
+            peer,
+            traffic: TrafficCounters::default(),
+            coil_packing,
+            requests: AtomicU64::new(0),
+            closing: AtomicBool::new(false),
+            units: Mutex::default(),
+        });
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(id, entry.clone());
+        }
+        entry
+    }
+
+    pub fn remove(&self, id: u64) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&id);
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.lock().map(|entries| entries.len()).unwrap_or(0)
+    }
+
+    pub fn infos(&self) -> Vec<ConnectionInfo> {
+        self.entries
+            .lock()
+            .map(|entries| entries.values().map(|entry| entry.info()).collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::modbus::{contiguous_runs, emit_update, DataArea};
+use crate::sink::UpdateSink;
+
+#[derive(Default)]
+struct Slot {
+    last_emit: Option<Instant>,
+    pending: Option<u16>,
+    scheduled: bool,
+}
+
+/// Limits update events to one per `interval` for each address. Changes arriving sooner are
+/// held back and the latest one is emitted when the interval has passed.
+pub struct Debouncer {
+    interval: Duration,
+    slots: Mutex<HashMap<(DataArea, u16), Slot>>,
+    sink: Arc<dyn UpdateSink>,
+}
+
+impl Debouncer {
+    pub fn new(interval: Duration, sink: Arc<dyn UpdateSink>) -> Self {
+        Self {
+            interval,
+            slots: Mutex::default(),
+            sink,
+        }
+    }
+
+    pub fn emit(self: &Arc<Self>, area: DataArea, offset: u16, values: Vec<u16>) {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        let mut deferred = Vec::new();
+        {
+            let Ok(mut slots) = self.slots.lock() else {
+                return;
+            };
+            for (index, value) in values.into_iter().enumerate() {
+                let address = offset + index as u16;
+                let slot = slots.entry((area, address)).or_default();
+                match slot.last_emit.map(|last| now.duration_since(last)) {
+                    Some(elapsed) if elapsed < self.interval => {
+                        slot.pending = Some(value);
+                        if !slot.scheduled {
+                            slot.scheduled = true;
+                            deferred.push((address, self.interval - elapsed));
+                        }
+                    }
+                    _ => {
+                        slot.last_emit = Some(now);
+                        slot.pending = None;
+                        ready.push((address, value));
+                    }
+                }
+            }
+        }
+
+        for (start, run) in contiguous_runs(ready) {
+            emit_update(&*self.sink, area, start, run);
+        }
+        for (address, delay) in deferred {
+            let debouncer = self.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(delay).await;
+                debouncer.flush(area, address);
+            });
+        }
+    }
+
+    fn flush(&self, area: DataArea, address: u16) {
+        let pending = {
+            let Ok(mut slots) = self.slots.lock() else {
+                return;
+            };
+            let Some(slot) = slots.get_mut(&(area, address)) else {
+                return;
+            };
+            slot.scheduled = false;
+            let pending = slot.pending.take();
+            if pending.is_some() {
+                slot.last_emit = Some(Instant::now());
+            }
+            pending
+        };
+        if let Some(value) = pending {
+            emit_update(&*self.sink, area, address, vec![value]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::harness::RecordingSink;
+
+    fn values(sink: &RecordingSink) -> Vec<(u16, Vec<u16>)> {
+        let updates = sink.updates().into_iter();
+        updates
+            .map(|update| (update.offset, update.values))
+            .collect()
+    }
+
+    #[test]
+    fn emits_the_first_change_and_the_last_held_back_one() {
+        let sink = Arc::new(RecordingSink::default());
+        let debouncer = Arc::new(Debouncer::new(Duration::from_millis(50), sink.clone()));
+        debouncer.emit(DataArea::HoldingRegisters, 3, vec![1]);
+        debouncer.emit(DataArea::HoldingRegisters, 3, vec![2]);
+        debouncer.emit(DataArea::HoldingRegisters, 3, vec![3]);
+        assert_eq!(values(&sink), vec![(3, vec![1])]);
+
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(values(&sink), vec![(3, vec![1]), (3, vec![3])]);
+    }
+
+    #[test]
+    fn addresses_are_limited_independently() {
+        let sink = Arc::new(RecordingSink::default());
+        let debouncer = Arc::new(Debouncer::new(Duration::from_secs(60), sink.clone()));
+        debouncer.emit(DataArea::HoldingRegisters, 0, vec![1, 2]);
+        debouncer.emit(DataArea::HoldingRegisters, 1, vec![5, 6]);
+        assert_eq!(values(&sink), vec![(0, vec![1, 2]), (2, vec![6])]);
+    }
+}
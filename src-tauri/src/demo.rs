@@ -0,0 +1,62 @@
+use crate::modbus::StoreContents;
+use crate::rng::SplitMix64;
+
+const COIL_BLOCK: usize = 8;
+const ANALOG_BLOCK: usize = 16;
+const RAMP_BLOCK: usize = 32;
+
+/// Plausible-looking contents for demos and screenshots, sized like the current store and
+/// fully determined by `seed`: coil blocks with on/off/alternating/random patterns, sparse
+/// discrete inputs, noisy analog levels in input registers and ramps in holding registers.
+pub fn generate(seed: u64, sizes: [usize; 4]) -> StoreContents {
+    let mut rng = SplitMix64::new(seed);
+    let [coils, discrete_inputs, input_registers, holding_registers] = sizes;
+    StoreContents {
+        coils: coil_patterns(&mut rng, coils),
+        discrete_inputs: (0..discrete_inputs)
+            .map(|_| rng.next_u64() % 10 < 3)
+            .collect(),
+        input_registers: analog_levels(&mut rng, input_registers),
+        holding_registers: ramps(&mut rng, holding_registers),
+    }
+}
+
+fn coil_patterns(rng: &mut SplitMix64, len: usize) -> Vec<bool> {
+    let mut values = Vec::with_capacity(len);
+    while values.len() < len {
+        let pattern = rng.next_u64() % 4;
+        for index in 0..COIL_BLOCK.min(len - values.len()) {
+            values.push(match pattern {
+                0 => true,
+                1 => false,
+                2 => index % 2 == 0,
+                _ => rng.next_u64() % 2 == 0,
+            });
+        }
+    }
+    values
+}
+
+fn analog_levels(rng: &mut SplitMix64, len: usize) -> Vec<u16> {
+    let mut values = Vec::with_capacity(len);
+    while values.len() < len {
+        let level = (rng.next_u64() % 4000) as i32 + 50;
+        for _ in 0..ANALOG_BLOCK.min(len - values.len()) {
+            let noise = (rng.next_u64() % 101) as i32 - 50;
+            values.push((level + noise) as u16);
+        }
+    }
+    values
+}
+
+fn ramps(rng: &mut SplitMix64, len: usize) -> Vec<u16> {
+    let mut values = Vec::with_capacity(len);
+    while values.len() < len {
+        let start = (rng.next_u64() % 1000) as u16;
+        let step = (rng.next_u64() % 10) as u16 + 1;
+        for index in 0..RAMP_BLOCK.min(len - values.len()) {
+            values.push(start.wrapping_add(step.wrapping_mul(index as u16)));
+        }
+    }
+    values
+}
@@ -0,0 +1,50 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+const CAPACITY: usize = 256;
+
+#[derive(Serialize, Clone)]
+pub struct ExceptionRecord {
+    pub timestamp_ms: u64,
+    pub peer: String,
+    pub unit: u8,
+    pub function: u8,
+    pub address: Option<u16>,
+    pub quantity: Option<u16>,
+    pub code: String,
+}
+
+/// The most recent exception responses, oldest first, bounded to the last `CAPACITY`.
+#[derive(Default)]
+pub struct ExceptionLog {
+    records: Mutex<VecDeque<ExceptionRecord>>,
+}
+
+impl ExceptionLog {
+    pub fn record(&self, record: ExceptionRecord) {
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() == CAPACITY {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+
+    pub fn latest(&self, limit: usize) -> Vec<ExceptionRecord> {
+        self.records
+            .lock()
+            .map(|records| {
+                let skip = records.len().saturating_sub(limit);
+                records.iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut records) = self.records.lock() {
+            records.clear();
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use tokio_modbus::ExceptionCode;
+
+/// Serializable mirror of the exception codes a master can be answered with.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExceptionKind {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    MemoryParityError,
+    GatewayPathUnavailable,
+    GatewayTargetDevice,
+}
+
+impl From<ExceptionKind> for ExceptionCode {
+    fn from(kind: ExceptionKind) -> Self {
+        match kind {
+            ExceptionKind::IllegalFunction => ExceptionCode::IllegalFunction,
+            ExceptionKind::IllegalDataAddress => ExceptionCode::IllegalDataAddress,
+            ExceptionKind::IllegalDataValue => ExceptionCode::IllegalDataValue,
+            ExceptionKind::ServerDeviceFailure => ExceptionCode::ServerDeviceFailure,
+            ExceptionKind::Acknowledge => ExceptionCode::Acknowledge,
+            ExceptionKind::ServerDeviceBusy => ExceptionCode::ServerDeviceBusy,
+            ExceptionKind::MemoryParityError => ExceptionCode::MemoryParityError,
+            ExceptionKind::GatewayPathUnavailable => ExceptionCode::GatewayPathUnavailable,
+            ExceptionKind::GatewayTargetDevice => ExceptionCode::GatewayTargetDevice,
+        }
+    }
+}
+
+/// Exception codes answered when a request is refused by a server feature rather than by
+/// plain range checks, so a single mapping can match a specific device's behaviour.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExceptionPolicy {
+    /// Reads touching addresses that were never written while `sparse` is on.
+    pub address_gap: ExceptionKind,
+    /// Writes refused while the store is frozen with `FreezeWritePolicy::Reject`.
+    pub write_rejected: ExceptionKind,
+    /// `WriteMultipleCoils` frames refused by `strict_coil_packing`.
+    pub malformed_packing: ExceptionKind,
+    /// Requests targeting an area switched off in `AreaPresence`.
+    pub absent_area: ExceptionKind,
+}
+
+impl Default for ExceptionPolicy {
+    fn default() -> Self {
+        Self {
+            address_gap: ExceptionKind::IllegalDataAddress,
+            write_rejected: ExceptionKind::ServerDeviceBusy,
+            malformed_packing: ExceptionKind::IllegalDataValue,
+            absent_area: ExceptionKind::IllegalFunction,
+        }
+    }
+}
+
+impl ExceptionPolicy {
+    pub fn address_gap(&self) -> ExceptionCode {
+        self.address_gap.into()
+    }
+
+    pub fn write_rejected(&self) -> ExceptionCode {
+        self.write_rejected.into()
+    }
+
+    pub fn malformed_packing(&self) -> ExceptionCode {
+        self.malformed_packing.into()
+    }
+
+    pub fn absent_area(&self) -> ExceptionCode {
+        self.absent_area.into()
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modbus::{apply_mask, DataArea, ModbusStore};
+
+/// What a master write does while the store is frozen. Frozen readers never see it either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FreezeWritePolicy {
+    /// Written to the live store right away, visible to local commands but not to masters.
+    #[default]
+    ApplyLive,
+    /// Acknowledged and queued, then applied to the live store on unfreeze.
+    Buffer,
+    /// Refused with the `write_rejected` exception, `ServerDeviceBusy` by default.
+    Reject,
+}
+
+impl FreezeWritePolicy {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            FreezeWritePolicy::ApplyLive => 0,
+            FreezeWritePolicy::Buffer => 1,
+            FreezeWritePolicy::Reject => 2,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => FreezeWritePolicy::Buffer,
+            2 => FreezeWritePolicy::Reject,
+            _ => FreezeWritePolicy::ApplyLive,
+        }
+    }
+}
+
+/// A master write accepted under `FreezeWritePolicy::Buffer`, waiting for unfreeze.
+#[derive(Clone, Debug)]
+pub enum BufferedWrite {
+    Coils { offset: u16, values: Vec<bool> },
+    Registers { offset: u16, values: Vec<u16> },
+    Mask { offset: u16, and_mask: u16, or_mask: u16 },
+}
+
+impl BufferedWrite {
+    pub fn fits(&self, store: &ModbusStore) -> bool {
+        let (area, offset, len) = match self {
+            BufferedWrite::Coils { offset, values } => (DataArea::Coils, *offset, values.len()),
+            BufferedWrite::Registers { offset, values } => {
+                (DataArea::HoldingRegisters, *offset, values.len())
+            }
+            BufferedWrite::Mask { offset, .. } => (DataArea::HoldingRegisters, *offset, 1),
+        };
+        offset as usize + len <= store.len(area)
+    }
+
+    /// Applies the write to the live store, unless it was resized below the written range
+    /// since the write was queued.
+    pub fn apply(&self, store: &mut ModbusStore) -> bool {
+        if !self.fits(store) {
+            return false;
+        }
+        match self {
+            BufferedWrite::Coils { offset, values } => {
+                store.coils.write(*offset as usize, values);
+                store.mark_initialized(DataArea::Coils, *offset as usize, values.len());
+            }
+            BufferedWrite::Registers { offset, values } => {
+                store.holding_registers.write(*offset as usize, values);
+                store.mark_initialized(DataArea::HoldingRegisters, *offset as usize, values.len());
+            }
+            BufferedWrite::Mask {
+                offset,
+                and_mask,
+                or_mask,
+            } => {
+                let index = *offset as usize;
+                if let Some(current) = store.holding_registers.get(index) {
+                    let next = apply_mask(current, *and_mask, *or_mask);
+                    store.holding_registers.set(index, next);
+                }
+                store.mark_initialized(DataArea::HoldingRegisters, index, 1);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_modbus::prelude::*;
+
+    use super::*;
+    use crate::harness::TestServer;
+    use crate::modbus::ServiceOptions;
+
+    #[tokio::test]
+    async fn buffered_writes_are_replayed_on_unfreeze() {
+        let mut store = ModbusStore::new(8);
+        store.write_values(DataArea::HoldingRegisters, 0, &[0x00F0]);
+        let server = TestServer::start(store, 1, ServiceOptions::default()).await;
+        assert!(server.store.freeze(FreezeWritePolicy::Buffer));
+        let mut client = server.client(1).await;
+        client.write_single_register(2, 7).await.unwrap().unwrap();
+        client
+            .write_multiple_registers(3, &[8, 9])
+            .await
+            .unwrap()
+            .unwrap();
+        client.write_single_coil(1, true).await.unwrap().unwrap();
+        client
+            .masked_write_register(0, 0xFF00, 0x000F)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let words = client.read_holding_registers(0, 5).await.unwrap().unwrap();
+        assert_eq!(words, vec![0x00F0, 0, 0, 0, 0]);
+        let live = server
+            .store
+            .read()
+            .unwrap()
+            .values(DataArea::HoldingRegisters, 0, 5);
+        assert_eq!(live, vec![0x00F0, 0, 0, 0, 0]);
+
+        assert_eq!(server.store.unfreeze(true), Some(4));
+        let words = client.read_holding_registers(0, 5).await.unwrap().unwrap();
+        assert_eq!(words, vec![0x000F, 0, 7, 8, 9]);
+        let coils = client.read_coils(0, 2).await.unwrap().unwrap();
+        assert_eq!(coils, vec![false, true]);
+        server.stop().await;
+    }
+
+    #[test]
+    fn writes_past_a_shrunk_area_are_dropped() {
+        let mut store = ModbusStore::new(8);
+        let write = BufferedWrite::Registers {
+            offset: 6,
+            values: vec![1, 2],
+        };
+        store.resize(DataArea::HoldingRegisters, 7).unwrap();
+        assert!(!write.apply(&mut store));
+        assert_eq!(store.values(DataArea::HoldingRegisters, 6, 1), vec![0]);
+    }
+}
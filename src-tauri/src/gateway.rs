@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::time::timeout;
+use tokio_modbus::client::{tcp, Client, Context};
+use tokio_modbus::slave::{Slave, SlaveContext};
+use tokio_modbus::{ExceptionCode, Request, Response};
+
+use crate::metrics::ServerMetrics;
+use crate::modbus::{bools_to_u16, DataArea};
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+/// Largest quantity a single read may ask for, per the spec.
+const MAX_READ_BITS: u16 = 2000;
+const MAX_READ_REGISTERS: u16 = 125;
+
+type SharedClient = Arc<tokio::sync::Mutex<Option<Context>>>;
+
+/// Forwards requests for configured unit ids to upstream Modbus TCP devices, keeping one
+/// client connection per upstream address that is shared by every unit routed to it.
+pub struct UpstreamPool {
+    routes: HashMap<u8, SocketAddr>,
+    clients: Mutex<HashMap<SocketAddr, SharedClient>>,
+    cache: Option<ReadCache>,
+    metrics: Arc<ServerMetrics>,
+}
+
+impl UpstreamPool {
+    pub fn new(
+        routes: HashMap<u8, SocketAddr>,
+        cache_ttl: Option<Duration>,
+        metrics: Arc<ServerMetrics>,
+    ) -> Self {
+        Self {
+            routes,
+            clients: Mutex::default(),
+            cache: cache_ttl.map(ReadCache::new),
+            metrics,
+        }
+    }
+
+    pub fn routes_unit(&self, unit: u8) -> bool {
+        self.routes.contains_key(&unit)
+    }
+
+    /// Any transport failure or timeout drops the pooled connection so the next request
+    /// reconnects, and is reported to the master as `GatewayTargetDevice`. Cached reads
+    /// overlapping a write are only dropped once the upstream has accepted it.
+    pub async fn forward(
+        &self,
+        unit: u8,
+        request: Request<'static>,
+    ) -> Result<Response, ExceptionCode> {
+        let addr = *self
+            .routes
+            .get(&unit)
+            .ok_or(ExceptionCode::GatewayPathUnavailable)?;
+        let key = CacheKey::for_read(unit, &request);
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            if let Some(response) = cache.get(key) {
+                self.metrics.upstream_cache.record_hit();
+                return Ok(response);
+            }
+            self.metrics.upstream_cache.record_miss();
+        }
+        let written = written_range(&request);
+        let client = self.client(addr)?;
+        let mut client = client.lock().await;
+        let result = timeout(UPSTREAM_TIMEOUT, call(&mut client, addr, unit, request)).await;
+        let response = match result {
+            Ok(Ok(response)) => response?,
+            Ok(Err(_)) | Err(_) => {
+                *client = None;
+                return Err(ExceptionCode::GatewayTargetDevice);
+            }
+        };
+        // Still holding the client, so no read of the old values can be cached after this.
+        if let (Some(cache), Some((table, start, len))) = (&self.cache, written) {
+            cache.invalidate(unit, table, start, len);
+        }
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            cache.insert(key, response.clone());
+        }
+        Ok(response)
+    }
+
+    fn client(&self, addr: SocketAddr) -> Result<SharedClient, ExceptionCode> {
+        let mut clients = self
+            .clients
+            .lock()
+            .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
+        Ok(clients.entry(addr).or_default().clone())
+    }
+}
+
+/// Reads `len` addresses of `area` from `unit` at `addr`, split into requests no larger than
+/// the spec allows. `client` is connected on first use and reused across calls; it is dropped
+/// after a transport failure so the next read reconnects.
+pub async fn read_device_range(
+    client: &mut Option<Context>,
+    addr: SocketAddr,
+    unit: u8,
+    area: DataArea,
+    offset: u16,
+    len: u16,
+) -> Result<Vec<u16>, String> {
+    let chunk = match area {
+        DataArea::Coils | DataArea::DiscreteInputs => MAX_READ_BITS,
+        DataArea::InputRegisters | DataArea::HoldingRegisters => MAX_READ_REGISTERS,
+    };
+    let mut values = Vec::with_capacity(len as usize);
+    let mut done = 0u16;
+    while done < len {
+        let start = offset
+            .checked_add(done)
+            .ok_or_else(|| "Range exceeds the address space".to_string())?;
+        let qty = chunk.min(len - done);
+        let request = match area {
+            DataArea::Coils => Request::ReadCoils(start, qty),
+            DataArea::DiscreteInputs => Request::ReadDiscreteInputs(start, qty),
+            DataArea::InputRegisters => Request::ReadInputRegisters(start, qty),
+            DataArea::HoldingRegisters => Request::ReadHoldingRegisters(start, qty),
+        };
+        let response = match timeout(UPSTREAM_TIMEOUT, call(client, addr, unit, request)).await {
+            Ok(Ok(Ok(response))) => response,
+            Ok(Ok(Err(exception))) => return Err(format!("Device answered {exception}")),
+            Ok(Err(err)) => {
+                *client = None;
+                return Err(err.to_string());
+            }
+            Err(_) => {
+                *client = None;
+                return Err(format!("Device did not answer within {UPSTREAM_TIMEOUT:?}"));
+            }
+        };
+        let mut read = match response {
+            Response::ReadCoils(bits) | Response::ReadDiscreteInputs(bits) => bools_to_u16(&bits),
+            Response::ReadInputRegisters(words) | Response::ReadHoldingRegisters(words) => words,
+            _ => return Err("Device sent an unexpected response".to_string()),
+        };
+        if read.len() < qty as usize {
+            return Err("Device returned fewer values than requested".to_string());
+        }
+        read.truncate(qty as usize);
+        values.extend(read);
+        done += qty;
+    }
+    Ok(values)
+}
+
+async fn call(
+    client: &mut Option<Context>,
+    addr: SocketAddr,
+    unit: u8,
+    request: Request<'static>,
+) -> Result<Result<Response, ExceptionCode>, tokio_modbus::Error> {
+    let context = match client {
+        Some(context) => context,
+        None => client.insert(tcp::connect(addr).await?),
+    };
+    context.set_slave(Slave(unit));
+    context.call(request).await
+}
+
+/// Which register table a function code reads or writes; discrete inputs are read-only and
+/// never invalidated.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Table {
+    Coils,
+    DiscreteInputs,
+    InputRegisters,
+    HoldingRegisters,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    unit: u8,
+    table: Table,
+    addr: u16,
+    qty: u16,
+}
+
+impl CacheKey {
+    fn for_read(unit: u8, request: &Request<'_>) -> Option<Self> {
+        let (table, addr, qty) = match *request {
+            Request::ReadCoils(addr, qty) => (Table::Coils, addr, qty),
+            Request::ReadDiscreteInputs(addr, qty) => (Table::DiscreteInputs, addr, qty),
+            Request::ReadInputRegisters(addr, qty) => (Table::InputRegisters, addr, qty),
+            Request::ReadHoldingRegisters(addr, qty) => (Table::HoldingRegisters, addr, qty),
+            _ => return None,
+        };
+        Some(Self {
+            unit,
+            table,
+            addr,
+            qty,
+        })
+    }
+
+    fn overlaps(&self, unit: u8, table: Table, start: u16, len: u16) -> bool {
+        let (start, end) = (start as u32, start as u32 + len as u32);
+        let (addr, addr_end) = (self.addr as u32, self.addr as u32 + self.qty as u32);
+        self.unit == unit && self.table == table && addr < end && start < addr_end
+    }
+}
+
+fn written_range(request: &Request<'_>) -> Option<(Table, u16, u16)> {
+    match request {
+        Request::WriteSingleCoil(addr, _) => Some((Table::Coils, *addr, 1)),
+        Request::WriteMultipleCoils(addr, coils) => Some((Table::Coils, *addr, coils.len() as u16)),
+        Request::WriteSingleRegister(addr, _) | Request::MaskWriteRegister(addr, _, _) => {
+            Some((Table::HoldingRegisters, *addr, 1))
+        }
+        Request::WriteMultipleRegisters(addr, words)
+        | Request::ReadWriteMultipleRegisters(_, _, addr, words) => {
+            Some((Table::HoldingRegisters, *addr, words.len() as u16))
+        }
+        _ => None,
+    }
+}
+
+/// Upstream read responses keyed by (unit, table, address, quantity) and served until `ttl`
+/// has passed or a forwarded write touches an overlapping range. Expired entries are dropped
+/// on every insert, so a master scanning many ranges does not grow the cache without bound.
+struct ReadCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<CacheKey, (Instant, Response)>>,
+}
+
+impl ReadCache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Response> {
+        let mut entries = self.entries.lock().ok()?;
+        match entries.get(key) {
+            Some((stored_at, response)) if stored_at.elapsed() < self.ttl => Some(response.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: CacheKey, response: Response) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+            entries.insert(key, (Instant::now(), response));
+        }
+    }
+
+    fn invalidate(&self, unit: u8, table: Table, start: u16, len: u16) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|key, _| !key.overlaps(unit, table, start, len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_modbus::prelude::*;
+
+    use super::*;
+    use crate::harness::TestServer;
+    use crate::modbus::{ModbusStore, ServiceOptions};
+
+    const ROUTED_UNIT: u8 = 2;
+
+    async fn gateway_to(upstream: &TestServer) -> TestServer {
+        let options = ServiceOptions {
+            upstreams: [(ROUTED_UNIT, upstream.addr)].into(),
+            upstream_cache_ttl_ms: Some(60_000),
+            ..ServiceOptions::default()
+        };
+        TestServer::start(ModbusStore::new(8), 1, options).await
+    }
+
+    #[tokio::test]
+    async fn forwarded_write_invalidates_cached_reads() {
+        let upstream = TestServer::start(ModbusStore::new(8), 0, ServiceOptions::default()).await;
+        let gateway = gateway_to(&upstream).await;
+        let mut client = gateway.client(ROUTED_UNIT).await;
+        let mut direct = upstream.client(ROUTED_UNIT).await;
+
+        assert_eq!(
+            client.read_holding_registers(0, 2).await.unwrap(),
+            Ok(vec![0, 0])
+        );
+        // Changed behind the gateway's back, so only the cache can still answer 0.
+        direct.write_single_register(0, 5).await.unwrap().unwrap();
+        assert_eq!(
+            client.read_holding_registers(0, 2).await.unwrap(),
+            Ok(vec![0, 0])
+        );
+
+        client.write_single_register(1, 9).await.unwrap().unwrap();
+        assert_eq!(
+            client.read_holding_registers(0, 2).await.unwrap(),
+            Ok(vec![5, 9])
+        );
+        let stats = gateway.metrics.upstream_cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        gateway.stop().await;
+        upstream.stop().await;
+    }
+
+    #[tokio::test]
+    async fn refused_write_keeps_cached_reads() {
+        let upstream = TestServer::start(ModbusStore::new(8), 0, ServiceOptions::default()).await;
+        let gateway = gateway_to(&upstream).await;
+        let mut client = gateway.client(ROUTED_UNIT).await;
+
+        assert_eq!(
+            client.read_holding_registers(6, 2).await.unwrap(),
+            Ok(vec![0, 0])
+        );
+        let result = client
+            .write_multiple_registers(6, &[1, 2, 3])
+            .await
+            .unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        assert_eq!(
+            client.read_holding_registers(6, 2).await.unwrap(),
+            Ok(vec![0, 0])
+        );
+        let stats = gateway.metrics.upstream_cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        gateway.stop().await;
+        upstream.stop().await;
+    }
+
+    #[test]
+    fn insert_drops_expired_entries() {
+        let cache = ReadCache::new(Duration::ZERO);
+        for addr in 0..3 {
+            let key = CacheKey::for_read(1, &Request::ReadHoldingRegisters(addr, 1)).unwrap();
+            cache.insert(key, Response::ReadHoldingRegisters(vec![addr]));
+        }
+        let entries = cache.entries.lock().unwrap();
+        let key = CacheKey::for_read(1, &Request::ReadHoldingRegisters(2, 1)).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries.contains_key(&key));
+    }
+}
@@ -0,0 +1,183 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_modbus::client::{tcp, Context};
+use tokio_modbus::slave::Slave;
+use tokio_util::sync::CancellationToken;
+
+use crate::connections::ConnectionRegistry;
+use crate::hooks::ServiceHooks;
+use crate::metrics::ServerMetrics;
+use crate::modbus::{ModbusService, ModbusStore, ServerControls, ServiceOptions, UpdatePayload};
+use crate::server;
+use crate::sink::UpdateSink;
+use crate::store::SharedStore;
+
+/// Keeps every event the service sends, in order, for tests to assert on.
+#[derive(Default)]
+pub struct RecordingSink {
+    updates: Mutex<Vec<UpdatePayload>>,
+    events: Mutex<Vec<(String, Value)>>,
+}
+
+impl RecordingSink {
+    pub fn updates(&self) -> Vec<UpdatePayload> {
+        self.updates.lock().unwrap().clone()
+    }
+
+    /// Payloads of every `event` sent so far.
+    pub fn events(&self, event: &str) -> Vec<Value> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|(name, _)| name == event)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+}
+
+impl UpdateSink for RecordingSink {
+    fn emit_update(&self, payload: UpdatePayload) {
+        self.updates.lock().unwrap().push(payload);
+    }
+
+    fn emit_event(&self, event: &str, payload: Value) {
+        self.events
+            .lock()
+            .unwrap()
+            .push((event.to_string(), payload));
+    }
+}
+
+/// A server on an ephemeral loopback port, running the same accept path as `server_start`.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub store: Arc<SharedStore>,
+    pub sink: Arc<RecordingSink>,
+    pub controls: Arc<ServerControls>,
+    pub connections: ConnectionRegistry,
+    pub metrics: Arc<ServerMetrics>,
+    errors: Arc<Mutex<Vec<String>>>,
+    cancel: CancellationToken,
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl TestServer {
+    pub async fn start(store: ModbusStore, unit_id: u8, options: ServiceOptions) -> Self {
+        Self::start_with_hooks(store, unit_id, options, ServiceHooks::default()).await
+    }
+
+    pub async fn start_with_hooks(
+        store: ModbusStore,
+        unit_id: u8,
+        options: ServiceOptions,
+        hooks: ServiceHooks,
+    ) -> Self {
+        let store = Arc::new(SharedStore::new(store));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        Self::serve(listener, store, unit_id, options, hooks)
+    }
+
+    /// Serves `store` from an already bound `listener`, e.g. to restart on the same port.
+    pub fn serve(
+        listener: TcpListener,
+        store: Arc<SharedStore>,
+        unit_id: u8,
+        options: ServiceOptions,
+        hooks: ServiceHooks,
+    ) -> Self {
+        let addr = listener.local_addr().unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        store.configure(&options, sink.clone());
+        if let Ok(mut live) = store.write() {
+            live.set_defaults(options.store_defaults());
+        }
+        let controls = Arc::new(ServerControls::default());
+        let connections = ConnectionRegistry::default();
+        let metrics = Arc::new(ServerMetrics::default());
+        let service = ModbusService::new(
+            store.clone(),
+            sink.clone(),
+            unit_id,
+            options,
+            controls.clone(),
+            Arc::new(hooks),
+            metrics.clone(),
+        );
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let on_error = {
+            let errors = errors.clone();
+            move |err: io::Error| errors.lock().unwrap().push(err.to_string())
+        };
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn(server::serve(
+            listener,
+            service,
+            connections.clone(),
+            Arc::new(|| {}),
+            on_error,
+            cancel.clone(),
+        ));
+        Self {
+            addr,
+            store,
+            sink,
+            controls,
+            connections,
+            metrics,
+            errors,
+            cancel,
+            task,
+        }
+    }
+
+    /// Every error the server reported through `on_error`, as `server_start` would log them.
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.lock().unwrap().clone()
+    }
+
+    pub async fn client(&self, unit: u8) -> Context {
+        tcp::connect_slave(self.addr, Slave(unit)).await.unwrap()
+    }
+
+    /// Stops accepting, as `server_stop` does, and waits for the accept loop to end.
+    pub async fn stop(self) -> Arc<SharedStore> {
+        self.controls.shut_down();
+        self.cancel.cancel();
+        self.task.await.unwrap().unwrap();
+        self.store
+    }
+}
+
+/// A fresh directory under the system temp dir, removed again on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "modbus-tcp-server-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        );
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
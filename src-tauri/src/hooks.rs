@@ -0,0 +1,36 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+pub use crate::mei::{MeiHandler, MEI_CANOPEN_GENERAL_REFERENCE};
+pub use crate::modbus::DataArea;
+
+use crate::mei::MeiHandlers;
+
+#[derive(Clone, Debug)]
+pub struct WriteEvent {
+    pub area: DataArea,
+    pub offset: u16,
+    pub values: Vec<u16>,
+    pub unit: u8,
+    pub peer: SocketAddr,
+}
+
+pub type WriteHook = Arc<dyn Fn(&WriteEvent) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct ServiceHooks {
+    pub(crate) mei: MeiHandlers,
+    pub(crate) on_write: Vec<WriteHook>,
+}
+
+impl ServiceHooks {
+    pub fn with_mei_handler(mut self, mei_type: u8, handler: MeiHandler) -> Self {
+        self.mei = self.mei.with_handler(mei_type, handler);
+        self
+    }
+
+    pub fn with_write_hook(mut self, hook: WriteHook) -> Self {
+        self.on_write.push(hook);
+        self
+    }
+}
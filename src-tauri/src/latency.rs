@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Values below this many microseconds get a bucket each.
+const LINEAR_BUCKETS: u64 = 16;
+/// Each power of two above the linear range is split into `1 << SUB_BITS` buckets, keeping the
+/// reported percentiles within about 12% of the recorded value.
+const SUB_BITS: u32 = 3;
+/// Latencies are clamped to `2^MAX_EXPONENT` µs (about 71 minutes).
+const MAX_EXPONENT: u32 = 32;
+const BUCKETS: usize =
+    LINEAR_BUCKETS as usize + (((MAX_EXPONENT - SUB_BITS - 1) as usize) << SUB_BITS);
+
+#[derive(Serialize, Clone)]
+pub struct LatencyStats {
+    pub function_code: u8,
+    pub count: u64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+}
+
+/// Log-bucketed request latency per function code, in the spirit of an HDR histogram. Memory
+/// is fixed per function code, so it stays bounded however many requests are recorded.
+#[derive(Default)]
+pub struct LatencyHistograms {
+    by_code: Mutex<BTreeMap<u8, Box<[u64; BUCKETS]>>>,
+}
+
+impl LatencyHistograms {
+    pub fn record(&self, function_code: u8, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let Ok(mut by_code) = self.by_code.lock() else {
+            return;
+        };
+        let counts = by_code
+            .entry(function_code)
+            .or_insert_with(|| Box::new([0; BUCKETS]));
+        counts[bucket_index(micros)] += 1;
+    }
+
+    pub fn stats(&self) -> Vec<LatencyStats> {
+        let Ok(by_code) = self.by_code.lock() else {
+            return Vec::new();
+        };
+        by_code
+            .iter()
+            .map(|(&function_code, counts)| {
+                let count = counts.iter().sum();
+                LatencyStats {
+                    function_code,
+                    count,
+                    p50_us: percentile(counts, count, 50),
+                    p95_us: percentile(counts, count, 95),
+                    p99_us: percentile(counts, count, 99),
+                }
+            })
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut by_code) = self.by_code.lock() {
+            by_code.clear();
+        }
+    }
+}
+
+fn bucket_index(micros: u64) -> usize {
+    if micros < LINEAR_BUCKETS {
+        return micros as usize;
+    }
+    let exponent = (63 - micros.leading_zeros()).min(MAX_EXPONENT - 1);
+    if exponent == MAX_EXPONENT - 1 && micros >> exponent > 1 {
+        return BUCKETS - 1;
+    }
+    let sub = (micros >> (exponent - SUB_BITS)) as usize & ((1 << SUB_BITS) - 1);
+    LINEAR_BUCKETS as usize + (((exponent - SUB_BITS - 1) as usize) << SUB_BITS) + sub
+}
+
+/// Upper bound in microseconds of the values that fall into `index`.
+fn bucket_limit(index: usize) -> u64 {
+    if index < LINEAR_BUCKETS as usize {
+        return index as u64;
+    }
+    let offset = index - LINEAR_BUCKETS as usize;
+    let exponent = (offset >> SUB_BITS) as u32 + SUB_BITS + 1;
+    let sub = (offset & ((1 << SUB_BITS) - 1)) as u64;
+    let width = 1u64 << (exponent - SUB_BITS);
+    (1u64 << exponent) + (sub + 1) * width - 1
+}
+
+fn percentile(counts: &[u64; BUCKETS], total: u64, percent: u64) -> u64 {
+    if total == 0 {
+        return 0;
+    }
+    let rank = (total * percent).div_ceil(100).max(1);
+    let mut seen = 0;
+    for (index, count) in counts.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return bucket_limit(index);
+        }
+    }
+    bucket_limit(BUCKETS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_contiguous_up_to_the_clamp() {
+        for micros in 0..LINEAR_BUCKETS {
+            assert_eq!(bucket_index(micros), micros as usize);
+        }
+        for index in 0..BUCKETS - 1 {
+            let limit = bucket_limit(index);
+            assert_eq!(bucket_index(limit), index, "limit {limit}");
+            assert_eq!(bucket_index(limit + 1), index + 1, "limit {limit}");
+        }
+        assert_eq!(bucket_limit(BUCKETS - 1), (1 << MAX_EXPONENT) - 1);
+        assert_eq!(bucket_index(1 << MAX_EXPONENT), BUCKETS - 1);
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles_report_the_bucket_limit() {
+        let histograms = LatencyHistograms::default();
+        assert!(histograms.stats().is_empty());
+        for micros in 1..=100 {
+            histograms.record(3, Duration::from_micros(micros));
+        }
+        let stats = &histograms.stats()[0];
+        assert_eq!((stats.function_code, stats.count), (3, 100));
+        assert_eq!((stats.p50_us, stats.p95_us, stats.p99_us), (51, 95, 103));
+        assert_eq!(percentile(&[0; BUCKETS], 0, 50), 0);
+    }
+}
@@ -1,56 +1,400 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex, RwLock};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 use tauri::menu::{Menu, MenuItem, PredefinedMenuItem, Submenu};
-use tauri::{AppHandle, Emitter, Manager, Runtime, State};
-use tokio::net::TcpListener;
+use tauri::{AppHandle, Emitter, Manager, RunEvent, Runtime, State};
+use tokio::net::{TcpListener, TcpSocket};
 use tokio_util::sync::CancellationToken;
-use tokio_modbus::server::tcp::Server;
 
+mod activity;
+mod area;
+mod benchmark;
+mod checksum;
+mod command_log;
+mod commands;
+mod connections;
+mod debounce;
+mod demo;
+mod exception_log;
+mod exceptions;
+mod freeze;
+mod gateway;
+#[cfg(test)]
+mod harness;
+pub mod hooks;
+mod latency;
+mod logging;
+mod mei;
+mod metrics;
 mod modbus;
+mod overrides;
+mod paths;
+mod profiles;
+mod quirks;
+mod rng;
+mod schema;
+mod server;
+mod sink;
+mod store;
+#[cfg(feature = "test-client")]
+pub mod test_client;
+mod transport;
+mod updates;
+pub mod word_order;
 
+use activity::ActivityLog;
+use area::Mapping;
+use benchmark::BenchmarkReport;
+use command_log::{CommandLog, CommandRecord};
+use connections::{ConnectionInfo, ConnectionRegistry};
+use exception_log::ExceptionRecord;
+use freeze::FreezeWritePolicy;
+use hooks::ServiceHooks;
+use logging::LogControl;
+use mei::MEI_CANOPEN_GENERAL_REFERENCE;
+use metrics::{MetricsSnapshot, ServerMetrics};
 use modbus::{
-    bools_to_u16, emit_update, ConnectionService, DataArea, ModbusService, ModbusStore, STORE_SIZE,
+    bools_to_u16, contiguous_runs, emit_store, emit_write, pack_bits, DataArea, ModbusService,
+    ModbusStore, ServerControls, ServiceOptions, StoreContents, ValueChange, MAX_AREA_SIZE,
+    STORE_SIZE,
 };
+use profiles::ProfileStore;
+use schema::SchemaField;
+use sink::UpdateSink;
+use store::SharedStore;
+use updates::UpdateQueue;
 
 #[derive(Clone)]
 struct AppState {
     app: AppHandle,
-    store: Arc<RwLock<ModbusStore>>,
+    store: Arc<SharedStore>,
     server: Arc<Mutex<ServerRuntimeState>>,
+    metrics: Arc<ServerMetrics>,
+    hooks: Arc<ServiceHooks>,
+    logging: Arc<LogControl>,
+    address_base: Arc<Mutex<AddressBase>>,
+    updates: Arc<UpdateQueue>,
+    strict_types: Arc<AtomicBool>,
+    commands: Arc<CommandLog>,
+    schema: Arc<Mutex<Vec<SchemaField>>>,
+}
+
+/// Numbering used by the local register commands. The store and the wire protocol are always
+/// zero-based; with `OneBased`, address 1 passed to a command is store offset 0 and address 0
+/// is rejected. `store_dirty` and `store_diff` report addresses the same way; update events
+/// keep reporting zero-based store offsets.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum AddressBase {
+    #[default]
+    ZeroBased,
+    OneBased,
+}
+
+impl AppState {
+    fn check_value(&self, area: DataArea, value: &RegisterValue) -> Result<(), String> {
+        if self.strict_types.load(Ordering::SeqCst) {
+            value.check_strict(area)?;
+        }
+        Ok(())
+    }
+
+    fn check_values(&self, area: DataArea, values: &RegisterValues) -> Result<(), String> {
+        if self.strict_types.load(Ordering::SeqCst) {
+            values.check_strict(area)?;
+        }
+        Ok(())
+    }
+
+    fn ensure_locally_writable(&self, area: DataArea) -> Result<(), String> {
+        if self.store.locally_writable(area) {
+            return Ok(());
+        }
+        Err(format!("{area:?} is read-only (lock_read_only_areas is enabled)"))
+    }
+
+    fn address_base(&self) -> Result<AddressBase, String> {
+        self.address_base
+            .lock()
+            .map(|base| *base)
+            .map_err(|_| "State lock poisoned".to_string())
+    }
+
+    fn store_offset(&self, address: u16) -> Result<u16, String> {
+        store_offset(self.address_base()?, address)
+    }
+}
+
+/// Translates a command `address` given in `base` to a zero-based store offset.
+fn store_offset(base: AddressBase, address: u16) -> Result<u16, String> {
+    match base {
+        AddressBase::ZeroBased => Ok(address),
+        AddressBase::OneBased => address
+            .checked_sub(1)
+            .ok_or_else(|| "Address 0 is invalid with one-based addressing".to_string()),
+    }
+}
+
+/// Translates a zero-based store `offset` back to its address in `base`, the reverse of
+/// `store_offset`. Offset 65535 has no one-based address.
+fn command_address(base: AddressBase, offset: u16) -> Result<u16, String> {
+    match base {
+        AddressBase::ZeroBased => Ok(offset),
+        AddressBase::OneBased => offset
+            .checked_add(1)
+            .ok_or_else(|| format!("Store offset {offset} has no one-based address")),
+    }
 }
 
 #[derive(Default)]
 struct ServerRuntimeState {
     runtime: Option<RuntimeState>,
+    /// Config of a `start_server` call that has passed the running check but not yet stored
+    /// its runtime; a second start is refused meanwhile.
+    starting: Option<ServerConfig>,
     last_error: Option<String>,
+    last_error_at: Option<Instant>,
+}
+
+impl ServerRuntimeState {
+    fn set_error(&mut self, err: String) {
+        self.last_error = Some(err);
+        self.last_error_at = Some(Instant::now());
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.last_error_at = None;
+        self.last_error.take()
+    }
+}
+
+/// Reserves `ServerRuntimeState::starting` for one `start_server` call and releases it on any
+/// early return.
+struct StartSlot<'a> {
+    server: &'a Mutex<ServerRuntimeState>,
+}
+
+impl StartSlot<'_> {
+    /// Replaces the reservation with the started `runtime`.
+    fn fill(self, server_state: &mut ServerRuntimeState, runtime: RuntimeState) {
+        server_state.starting = None;
+        server_state.runtime = Some(runtime);
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for StartSlot<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut server_state) = self.server.lock() {
+            server_state.starting = None;
+        }
+    }
 }
 
 struct RuntimeState {
     cancel: CancellationToken,
     handle: tauri::async_runtime::JoinHandle<()>,
     bind: String,
-    connections: Arc<AtomicUsize>,
+    binds: Vec<BindInfo>,
+    config: ServerConfig,
+    connections: ConnectionRegistry,
+    controls: Arc<ServerControls>,
+    started_at: Instant,
+    started_at_ms: u64,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Health {
+    Ok,
+    Degraded,
+    Stopped,
 }
 
 #[derive(Serialize, Clone)]
 struct ServerStatus {
     running: bool,
+    health: Health,
+    paused: bool,
     bind: String,
+    binds: Vec<BindInfo>,
     connections: usize,
     last_error: Option<String>,
+    uptime_secs: u64,
+    started_at_ms: Option<u64>,
 }
 
+/// Sent as `modbus://started` once the server accepts connections.
+#[derive(Serialize, Clone)]
+struct StartedPayload {
+    timestamp_ms: u64,
+    config: ServerConfig,
+    binds: Vec<BindInfo>,
+    /// The configured unit id (0 answers any unit) followed by the upstream-routed ones.
+    unit_ids: Vec<u8>,
+}
+
+/// Sent as `modbus://stopped` when `server_stop` shuts a running server down.
+#[derive(Serialize, Clone)]
+struct StoppedPayload {
+    timestamp_ms: u64,
+    started_at_ms: u64,
+    uptime_secs: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct BindInfo {
+    addr: String,
+    family: &'static str,
+    tls: bool,
+    /// Only reachable from this machine.
+    is_loopback: bool,
+    /// Bound to every interface (`0.0.0.0` or `::`), so reachable from the network.
+    is_wildcard: bool,
+}
+
+impl BindInfo {
+    fn tcp(addr: SocketAddr) -> Self {
+        Self {
+            addr: addr.to_string(),
+            family: if addr.is_ipv4() { "ipv4" } else { "ipv6" },
+            tls: false,
+            is_loopback: addr.ip().is_loopback(),
+            is_wildcard: addr.ip().is_unspecified(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct WindowSpec {
+    area: DataArea,
+    offset: u16,
+    len: u16,
+}
+
+/// Values of every spec concatenated in request order; `boundaries[i]` is the index in
+/// `values` where spec `i` starts.
+#[derive(Serialize, Clone)]
+struct StoreWindow {
+    values: Vec<u16>,
+    boundaries: Vec<usize>,
+}
+
+/// The whole store at `revision`, sent as `modbus://snapshot` to a window that subscribes and
+/// to every window once `store_replace` or `store_generate_demo` swaps the contents.
+#[derive(Serialize, Clone)]
+struct StoreSnapshot {
+    revision: u64,
+    coils: Vec<u16>,
+    discrete_inputs: Vec<u16>,
+    input_registers: Vec<u16>,
+    holding_registers: Vec<u16>,
+}
+
+impl StoreSnapshot {
+    fn new(store: &ModbusStore, revision: u64) -> Self {
+        Self {
+            revision,
+            coils: bools_to_u16(&store.coils.to_vec()),
+            discrete_inputs: bools_to_u16(&store.discrete_inputs.to_vec()),
+            input_registers: store.input_registers.to_vec(),
+            holding_registers: store.holding_registers.to_vec(),
+        }
+    }
+}
+
+/// A saved store to compare against, in the shape taken by `store_replace` or sent as
+/// `modbus://snapshot`. Bits may be booleans or 0/1, and a missing area is not compared.
 #[derive(Deserialize)]
+struct StoreBaseline {
+    #[serde(default)]
+    coils: Option<RegisterValues>,
+    #[serde(default)]
+    discrete_inputs: Option<RegisterValues>,
+    #[serde(default)]
+    input_registers: Option<RegisterValues>,
+    #[serde(default)]
+    holding_registers: Option<RegisterValues>,
+}
+
+#[derive(Serialize, Clone)]
+struct StoreDiff {
+    coils: Vec<ValueChange>,
+    discrete_inputs: Vec<ValueChange>,
+    input_registers: Vec<ValueChange>,
+    holding_registers: Vec<ValueChange>,
+}
+
+#[derive(Serialize, Clone)]
+struct StoreChecksum {
+    revision: u64,
+    checksum: String,
+}
+
+#[derive(Serialize, Clone)]
+struct DiagnosticBundle {
+    generated_at_ms: u64,
+    config: Option<ServerConfig>,
+    status: ServerStatus,
+    metrics: MetricsSnapshot,
+    connections: Vec<ConnectionInfo>,
+    exceptions: Vec<ExceptionRecord>,
+    store: StoreChecksum,
+}
+
+#[derive(Serialize, Clone)]
+struct StoreActivity {
+    coils: Option<u64>,
+    discrete_inputs: Option<u64>,
+    input_registers: Option<u64>,
+    holding_registers: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+struct StoreInfo {
+    coils: usize,
+    discrete_inputs: usize,
+    input_registers: usize,
+    holding_registers: usize,
+    compact_areas: Vec<DataArea>,
+    mapped_areas: Vec<DataArea>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 struct ServerConfig {
     host: String,
     port: u16,
+    #[serde(alias = "device_address", alias = "slave_id")]
     unit_id: u8,
+    #[serde(default)]
+    reuse_addr: Option<bool>,
+    #[serde(default)]
+    heartbeat_secs: Option<u64>,
+    #[serde(default)]
+    address_base: Option<AddressBase>,
+    #[serde(flatten)]
+    options: ServiceOptions,
 }
 
 const MENU_OPEN_SETTINGS: &str = "open_settings";
+/// Files under `mmap_path`, named for their on-disk layout: 16-bit little-endian words.
+const MAPPED_FILES: [(DataArea, &str); 2] = [
+    (DataArea::InputRegisters, "input_registers.u16le"),
+    (DataArea::HoldingRegisters, "holding_registers.u16le"),
+];
+/// Highest unit id a Modbus server may be assigned; 248-255 are reserved.
+const MAX_UNIT_ID: u8 = 247;
+/// Number of most recent exceptions included in a diagnostic bundle.
+const DIAGNOSTIC_EXCEPTIONS: usize = 100;
+const MAX_BENCHMARK_CONNECTIONS: usize = 64;
+const MAX_BENCHMARK_MS: u64 = 60_000;
+/// How long a running server is reported as degraded after a serve or connection error.
+const DEGRADED_WINDOW: Duration = Duration::from_secs(30);
 
 #[derive(Deserialize)]
 #[serde(untagged)]
@@ -59,6 +403,32 @@ enum RegisterValue {
     Number(u16),
 }
 
+/// Condition awaited by `register_wait`, e.g. `{ "op": "gt", "value": 500 }`. The bit variants
+/// hold when every bit of the mask is set or cleared.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+enum WaitCondition {
+    Eq(u16),
+    Ne(u16),
+    Gt(u16),
+    Lt(u16),
+    BitsSet(u16),
+    BitsClear(u16),
+}
+
+impl WaitCondition {
+    fn matches(self, value: u16) -> bool {
+        match self {
+            WaitCondition::Eq(expected) => value == expected,
+            WaitCondition::Ne(expected) => value != expected,
+            WaitCondition::Gt(bound) => value > bound,
+            WaitCondition::Lt(bound) => value < bound,
+            WaitCondition::BitsSet(mask) => value & mask == mask,
+            WaitCondition::BitsClear(mask) => value & mask == 0,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum RegisterValues {
@@ -66,50 +436,182 @@ enum RegisterValues {
     Numbers(Vec<u16>),
 }
 
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum StartError {
+    AlreadyRunning { config: ServerConfig },
+    Failed { message: String },
+}
+
+impl From<String> for StartError {
+    fn from(message: String) -> Self {
+        StartError::Failed { message }
+    }
+}
+
+/// Fails with `AlreadyRunning` instead of ignoring `config` when a server is already up, so
+/// the caller can decide whether to stop and start again with the new settings.
 #[tauri::command]
-async fn server_start(config: ServerConfig, state: State<'_, AppState>) -> Result<ServerStatus, String> {
-    {
+async fn server_start(
+    config: ServerConfig,
+    state: State<'_, AppState>,
+) -> Result<ServerStatus, StartError> {
+    state.commands.record(
+        "server_start",
+        format!("{}:{} unit {}", config.host, config.port, config.unit_id),
+    );
+    start_server(config, &state).await
+}
+
+/// Idempotent variant of `server_start` that returns the running server's status as-is.
+#[tauri::command]
+async fn server_ensure_started(
+    config: ServerConfig,
+    state: State<'_, AppState>,
+) -> Result<ServerStatus, String> {
+    state.commands.record(
+        "server_ensure_started",
+        format!("{}:{} unit {}", config.host, config.port, config.unit_id),
+    );
+    match start_server(config, &state).await {
+        Ok(status) => Ok(status),
+        Err(StartError::AlreadyRunning { .. }) => server_status(state),
+        Err(StartError::Failed { message }) => Err(message),
+    }
+}
+
+async fn start_server(config: ServerConfig, state: &AppState) -> Result<ServerStatus, StartError> {
+    let slot = {
         let mut server_state =
             state.server.lock().map_err(|_| "State lock poisoned".to_string())?;
-        if server_state.runtime.is_some() {
-            return Ok(build_status(&server_state));
+        let current = match (&server_state.runtime, &server_state.starting) {
+            (Some(runtime), _) => Some(&runtime.config),
+            (None, starting) => starting.as_ref(),
+        };
+        if let Some(config) = current {
+            return Err(StartError::AlreadyRunning {
+                config: config.clone(),
+            });
         }
+        server_state.starting = Some(config.clone());
         server_state.last_error = None;
-    }
+        server_state.last_error_at = None;
+        StartSlot {
+            server: &state.server,
+        }
+    };
+
+    validate_config(&config)?;
 
     let addr: SocketAddr = format!("{}:{}", config.host, config.port)
         .parse()
         .map_err(|err: std::net::AddrParseError| err.to_string())?;
 
-    let listener = match TcpListener::bind(addr).await {
+    let sink: Arc<dyn UpdateSink> = Arc::new(state.app.clone());
+    let activity = ActivityLog::new(sink.clone(), config.options.log_level);
+    let reuse_addr = config.reuse_addr.unwrap_or(cfg!(not(windows)));
+    let listener = match bind_listener(addr, reuse_addr) {
         Ok(listener) => listener,
         Err(err) => {
-            let mut server_state = state
-                .server
-                .lock()
-                .map_err(|_| "State lock poisoned".to_string())?;
-            server_state.last_error = Some(err.to_string());
-            let status = build_status(&server_state);
-            let _ = state.app.emit("modbus://status", status.clone());
-            return Err(err.to_string());
+            activity.error(format!("Failed to bind {addr}: {err}"), None);
+            return Err(start_failed(state, err.to_string()));
         }
     };
 
-    let bind = listener
-        .local_addr()
-        .map_err(|err| err.to_string())?
-        .to_string();
+    let local_addr = listener.local_addr().map_err(|err| err.to_string())?;
+    let bind = local_addr.to_string();
+    let binds = vec![BindInfo::tcp(local_addr)];
+    activity.info(format!("Listening on {bind}"), None);
     let cancel = CancellationToken::new();
     let cancel_for_task = cancel.clone();
-    let connections = Arc::new(AtomicUsize::new(0));
+    let cancel_for_heartbeat = cancel.clone();
+    let connections = ConnectionRegistry::default();
     let connections_for_runtime = connections.clone();
+    let metrics = state.metrics.clone();
+    metrics.reset();
+    let controls = Arc::new(ServerControls::default());
+    let controls_for_runtime = controls.clone();
+    let controls_for_task = controls.clone();
     let app = state.app.clone();
     let store = state.store.clone();
     let server_state = state.server.clone();
+    if let Some(base) = config.address_base {
+        *state
+            .address_base
+            .lock()
+            .map_err(|_| "State lock poisoned".to_string())? = base;
+    }
+    let runtime_config = config.clone();
+    let heartbeat = config
+        .heartbeat_secs
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
     let unit_id = config.unit_id;
+    let options = config.options;
+    let hooks = state.hooks.clone();
+    {
+        let mut store = store
+            .write()
+            .map_err(|_| "Store lock poisoned".to_string())?;
+        let mappings = match options.mmap_path.as_deref() {
+            Some(dir) => open_mappings(&store, Path::new(dir)),
+            None => Ok(Vec::new()),
+        };
+        let mappings = match mappings {
+            Ok(mappings) => mappings,
+            Err(err) => {
+                drop(store);
+                activity.error(err.clone(), None);
+                return Err(start_failed(state, err));
+            }
+        };
+        store.set_defaults(options.store_defaults());
+        for area in DataArea::ALL {
+            store.set_compact(area, options.compact_areas.contains(&area));
+        }
+        for (area, _) in MAPPED_FILES {
+            store.unmap(area);
+        }
+        for (area, mapping) in mappings {
+            store.map(area, mapping);
+        }
+        emit_store(&app, &store);
+    }
+    store.configure(&options, sink.clone());
+    state
+        .strict_types
+        .store(options.strict_types, Ordering::SeqCst);
+    state
+        .updates
+        .set_capacity(options.update_queue_capacity.unwrap_or(0));
 
+    let started_at_ms = unix_millis();
+    let mut unit_ids = vec![runtime_config.unit_id];
+    let mut upstream_units: Vec<u8> = runtime_config.options.upstreams.keys().copied().collect();
+    upstream_units.sort_unstable();
+    unit_ids.extend(upstream_units);
+    let started = StartedPayload {
+        timestamp_ms: started_at_ms,
+        config: runtime_config.clone(),
+        binds: binds.clone(),
+        unit_ids,
+    };
+
+    // Held until the runtime is stored, so a task that ends straight away still finds it.
+    let mut server_state_guard = state
+        .server
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?;
     let task = tauri::async_runtime::spawn(async move {
-        let base_service = ModbusService::new(store, app.clone(), unit_id);
+        let base_service = ModbusService::new(
+            store,
+            sink,
+            unit_id,
+            options,
+            controls,
+            hooks,
+            metrics,
+        );
         let status_emitter = Arc::new({
             let app = app.clone();
             let server_state = server_state.clone();
@@ -120,65 +622,131 @@ async fn server_start(config: ServerConfig, state: State<'_, AppState>) -> Resul
                 }
             }
         });
-        let on_connected = move |stream, _socket_addr| {
-            let base_service = base_service.clone();
-            let connections = connections.clone();
-            let status_emitter = status_emitter.clone();
-            async move {
-                connections.fetch_add(1, Ordering::SeqCst);
-                (status_emitter)();
-                Ok(Some((
-                    ConnectionService::new(base_service, connections, status_emitter),
-                    stream,
-                )))
-            }
-        };
-
         let on_error = {
             let app = app.clone();
             let server_state = server_state.clone();
             move |err: std::io::Error| {
+                // tokio-modbus reports a failed connection without its peer address.
+                activity.warn(format!("Connection error: {err}"), None);
                 let mut state = server_state.lock().unwrap();
-                state.last_error = Some(err.to_string());
+                state.set_error(err.to_string());
                 let status = build_status(&state);
                 let _ = app.emit("modbus://status", status);
             }
         };
 
-        let abort_signal = async move {
-            cancel_for_task.cancelled().await;
-        };
-        let result = Server::new(listener)
-            .serve_until(&on_connected, on_error, abort_signal)
-            .await;
+        let result = server::serve(
+            listener,
+            base_service,
+            connections,
+            status_emitter,
+            on_error,
+            cancel_for_task,
+        )
+        .await;
 
         let mut state = server_state.lock().unwrap();
         if let Err(err) = result {
-            state.last_error = Some(err.to_string());
+            state.set_error(err.to_string());
+        }
+        // `server_stop` may already have taken this runtime and a new one been started.
+        let own = state
+            .runtime
+            .as_ref()
+            .is_some_and(|runtime| Arc::ptr_eq(&runtime.controls, &controls_for_task));
+        if own {
+            state.runtime = None;
         }
-        state.runtime = None;
         let status = build_status(&state);
         let _ = app.emit("modbus://status", status);
     });
-
-    let mut server_state = state
-        .server
-        .lock()
-        .map_err(|_| "State lock poisoned".to_string())?;
-    server_state.runtime = Some(RuntimeState {
+    let runtime = RuntimeState {
         cancel,
         handle: task,
         bind: bind.clone(),
+        binds,
+        config: runtime_config,
         connections: connections_for_runtime,
-    });
+        controls: controls_for_runtime,
+        started_at: Instant::now(),
+        started_at_ms,
+    };
+    slot.fill(&mut server_state_guard, runtime);
 
-    let status = build_status(&server_state);
+    let status = build_status(&server_state_guard);
+    drop(server_state_guard);
+
+    // The listener is bound before the task starts, so early connects wait in the backlog.
+    if let Some(interval) = heartbeat {
+        spawn_heartbeat(interval, cancel_for_heartbeat, state.app.clone(), state.server.clone());
+    }
     let _ = state.app.emit("modbus://status", status.clone());
+    let _ = state.app.emit("modbus://started", started);
     Ok(status)
 }
 
+/// Records why the server failed to start as `last_error` and emits the status, returning the
+/// error for the caller.
+fn start_failed(state: &AppState, message: String) -> StartError {
+    if let Ok(mut server_state) = state.server.lock() {
+        server_state.set_error(message.clone());
+        let status = build_status(&server_state);
+        let _ = state.app.emit("modbus://status", status);
+    }
+    message.into()
+}
+
+/// Opens every file under `mmap_path` before any area is mapped, so a failure leaves the
+/// store as it was.
+fn open_mappings(store: &ModbusStore, dir: &Path) -> Result<Vec<(DataArea, Mapping)>, String> {
+    paths::create_dir(dir)?;
+    let mut mappings = Vec::new();
+    for (area, file) in MAPPED_FILES {
+        let path = dir.join(file);
+        match store.open_mapping(area, &path) {
+            Ok(mapping) => mappings.push((area, mapping)),
+            Err(err) => {
+                for (_, mapping) in mappings {
+                    mapping.discard();
+                }
+                return Err(format!("Failed to map {}: {err}", path.display()));
+            }
+        }
+    }
+    Ok(mappings)
+}
+
+/// Re-emits the current status every `interval` so the UI can tell an idle server from a hung
+/// backend. Stops with the server's cancellation token, or once the runtime has gone away.
+fn spawn_heartbeat(
+    interval: Duration,
+    cancel: CancellationToken,
+    app: AppHandle,
+    server_state: Arc<Mutex<ServerRuntimeState>>,
+) {
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = ticker.tick() => {
+                    let Ok(state) = server_state.lock() else {
+                        break;
+                    };
+                    if state.runtime.is_none() {
+                        break;
+                    }
+                    let _ = app.emit("modbus://status", build_status(&state));
+                }
+            }
+        }
+    });
+}
+
 #[tauri::command]
 async fn server_stop(state: State<'_, AppState>) -> Result<ServerStatus, String> {
+    state.commands.record("server_stop", "");
     let runtime = {
         let mut server_state = state
             .server
@@ -187,60 +755,430 @@ async fn server_stop(state: State<'_, AppState>) -> Result<ServerStatus, String>
         server_state.runtime.take()
     };
 
-    if let Some(runtime) = runtime {
+    let stopped = runtime.map(|runtime| {
+        runtime.controls.shut_down();
         runtime.cancel.cancel();
         runtime.handle.abort();
+        StoppedPayload {
+            timestamp_ms: unix_millis(),
+            started_at_ms: runtime.started_at_ms,
+            uptime_secs: runtime.started_at.elapsed().as_secs(),
+        }
+    });
+
+    let server_state = state
+        .server
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?;
+    let status = build_status(&server_state);
+    let _ = state.app.emit("modbus://status", status.clone());
+    if let Some(stopped) = stopped {
+        let _ = state.app.emit("modbus://stopped", stopped);
     }
+    Ok(status)
+}
 
+#[tauri::command]
+fn server_pause(state: State<'_, AppState>) -> Result<ServerStatus, String> {
+    state.commands.record("server_pause", "");
+    set_server_paused(&state, true)
+}
+
+#[tauri::command]
+fn server_resume(state: State<'_, AppState>) -> Result<ServerStatus, String> {
+    state.commands.record("server_resume", "");
+    set_server_paused(&state, false)
+}
+
+fn set_server_paused(state: &AppState, paused: bool) -> Result<ServerStatus, String> {
     let server_state = state
         .server
         .lock()
         .map_err(|_| "State lock poisoned".to_string())?;
+    let runtime = server_state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| "Server is not running".to_string())?;
+    runtime.controls.set_paused(paused);
     let status = build_status(&server_state);
     let _ = state.app.emit("modbus://status", status.clone());
     Ok(status)
 }
 
-#[tauri::command]
-fn server_status(state: State<'_, AppState>) -> Result<ServerStatus, String> {
-    let server_state = state
-        .server
-        .lock()
-        .map_err(|_| "State lock poisoned".to_string())?;
-    Ok(build_status(&server_state))
+/// Overrides the run indicator reported by Report Server ID; `None` returns to the configured
+/// behaviour.
+#[tauri::command]
+fn set_run_indicator(on: Option<bool>, state: State<'_, AppState>) -> Result<(), String> {
+    state.commands.record("set_run_indicator", format!("{on:?}"));
+    let server_state = state
+        .server
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?;
+    let runtime = server_state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| "Server is not running".to_string())?;
+    runtime.controls.set_run_indicator(on);
+    Ok(())
+}
+
+#[tauri::command]
+fn server_status(state: State<'_, AppState>) -> Result<ServerStatus, String> {
+    let server_state = state
+        .server
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?;
+    Ok(build_status(&server_state))
+}
+
+/// Returns and clears the last error under the server lock, so an error recorded after this
+/// call stays set for the next one. Bind failures and connection errors are also sent as
+/// `modbus://log` entries when they happen, so the activity log keeps them after they are taken
+/// here.
+#[tauri::command]
+fn take_last_error(state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let mut server_state = state
+        .server
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?;
+    let error = server_state.take_error();
+    if error.is_some() {
+        let _ = state.app.emit("modbus://status", build_status(&server_state));
+    }
+    Ok(error)
+}
+
+#[tauri::command]
+fn server_metrics(state: State<'_, AppState>) -> MetricsSnapshot {
+    state.metrics.snapshot()
+}
+
+#[tauri::command]
+fn exception_log(limit: Option<usize>, state: State<'_, AppState>) -> Vec<ExceptionRecord> {
+    state.metrics.exceptions.latest(limit.unwrap_or(usize::MAX))
+}
+
+#[tauri::command]
+fn command_log(limit: Option<usize>, state: State<'_, AppState>) -> Vec<CommandRecord> {
+    state.commands.latest(limit.unwrap_or(usize::MAX))
+}
+
+#[tauri::command]
+fn command_log_clear(state: State<'_, AppState>) {
+    state.commands.clear();
+}
+
+#[tauri::command]
+fn server_connections(state: State<'_, AppState>) -> Result<Vec<ConnectionInfo>, String> {
+    let server_state = state
+        .server
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?;
+    Ok(server_state
+        .runtime
+        .as_ref()
+        .map(|runtime| runtime.connections.infos())
+        .unwrap_or_default())
+}
+
+/// Everything useful for an issue report in one value, optionally also written to `path` as
+/// JSON. The server state lock is held only to copy the config, status and connection list;
+/// the checksum is computed from the published store view without taking the store lock.
+#[tauri::command]
+fn diagnostic_bundle(
+    path: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<DiagnosticBundle, String> {
+    let (config, status, connections) = {
+        let server_state = state
+            .server
+            .lock()
+            .map_err(|_| "State lock poisoned".to_string())?;
+        let runtime = server_state.runtime.as_ref();
+        (
+            runtime.map(|runtime| runtime.config.clone()),
+            build_status(&server_state),
+            runtime
+                .map(|runtime| runtime.connections.infos())
+                .unwrap_or_default(),
+        )
+    };
+    let view = state.store.view();
+    let bundle = DiagnosticBundle {
+        generated_at_ms: unix_millis(),
+        config,
+        status,
+        metrics: state.metrics.snapshot(),
+        connections,
+        exceptions: state.metrics.exceptions.latest(DIAGNOSTIC_EXCEPTIONS),
+        store: StoreChecksum {
+            revision: state.store.revision(),
+            checksum: format!("{:016x}", view.checksum()),
+        },
+    };
+    if let Some(path) = path {
+        let data = serde_json::to_vec_pretty(&bundle).map_err(|err| err.to_string())?;
+        paths::write_file(Path::new(&path), &data)?;
+    }
+    Ok(bundle)
+}
+
+/// Loads the running server from this machine over `concurrency` loopback connections for
+/// `duration_ms`. The requests take the normal path, so they also count towards the server
+/// metrics, connection limits and update events.
+#[tauri::command]
+async fn benchmark(
+    duration_ms: u64,
+    concurrency: usize,
+    state: State<'_, AppState>,
+) -> Result<BenchmarkReport, String> {
+    state.commands.record(
+        "benchmark",
+        format!("{duration_ms} ms, {concurrency} connections"),
+    );
+    if concurrency == 0 || concurrency > MAX_BENCHMARK_CONNECTIONS {
+        return Err(format!("Concurrency must be between 1 and {MAX_BENCHMARK_CONNECTIONS}"));
+    }
+    if duration_ms == 0 || duration_ms > MAX_BENCHMARK_MS {
+        return Err(format!("Duration must be between 1 and {MAX_BENCHMARK_MS} ms"));
+    }
+    let (addr, unit_id) = {
+        let server = state
+            .server
+            .lock()
+            .map_err(|_| "State lock poisoned".to_string())?;
+        let runtime = server
+            .runtime
+            .as_ref()
+            .ok_or_else(|| "Server is not running".to_string())?;
+        let bind = runtime
+            .binds
+            .first()
+            .ok_or_else(|| "Server has no listener".to_string())?;
+        let addr: SocketAddr = bind
+            .addr
+            .parse()
+            .map_err(|err: std::net::AddrParseError| err.to_string())?;
+        (addr, runtime.config.unit_id)
+    };
+    let store = state.store.view();
+    let target = benchmark::Target {
+        addr: benchmark::loopback_target(addr),
+        // Unit id 0 answers any unit, but 0 itself is broadcast and gets no reply.
+        unit: if unit_id == 0 { 1 } else { unit_id },
+        coils: store.len(DataArea::Coils),
+        holding_registers: store.len(DataArea::HoldingRegisters),
+    };
+    if target.coils == 0 && target.holding_registers == 0 {
+        return Err("Store has no coils or holding registers to benchmark".to_string());
+    }
+    let duration = Duration::from_millis(duration_ms);
+    Ok(benchmark::run(target, duration, concurrency).await)
+}
+
+#[tauri::command]
+fn server_log_level(level: String, state: State<'_, AppState>) -> Result<(), String> {
+    state.commands.record("server_log_level", level.as_str());
+    state.logging.set_level(&level)
+}
+
+#[tauri::command]
+fn server_freeze(reject_writes: Option<bool>, state: State<'_, AppState>) -> Result<(), String> {
+    state.commands.record("server_freeze", format!("reject_writes={reject_writes:?}"));
+    let policy = match reject_writes {
+        Some(true) => FreezeWritePolicy::Reject,
+        Some(false) => FreezeWritePolicy::ApplyLive,
+        None => state.store.freeze_policy(),
+    };
+    if state.store.freeze(policy) {
+        Ok(())
+    } else {
+        Err("Store lock poisoned".to_string())
+    }
+}
+
+#[tauri::command]
+fn server_unfreeze(keep_edits: Option<bool>, state: State<'_, AppState>) -> Result<(), String> {
+    state.commands.record("server_unfreeze", format!("keep_edits={keep_edits:?}"));
+    let keep_edits = keep_edits.unwrap_or(true);
+    let Some(applied) = state.store.unfreeze(keep_edits) else {
+        return Ok(());
+    };
+    if !keep_edits || applied > 0 {
+        let store = state
+            .store
+            .read()
+            .map_err(|_| "Store lock poisoned".to_string())?;
+        emit_store(&state.app, &store);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn register_snapshot(
+    area: DataArea,
+    offset: u16,
+    len: u16,
+    state: State<'_, AppState>,
+) -> Result<Vec<u16>, String> {
+    let offset = state.store_offset(offset)?;
+    let store = state.store.view();
+    let start = offset as usize;
+    let end = start + len as usize;
+    if end > store.len(area) {
+        return Err("Requested range is out of bounds".to_string());
+    }
+
+    Ok(store.values(area, start, len as usize))
+}
+
+#[tauri::command]
+fn register_snapshot_packed(
+    area: DataArea,
+    offset: u16,
+    len: u16,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, String> {
+    let offset = state.store_offset(offset)?;
+    let store = state.store.view();
+    let start = offset as usize;
+    let end = start + len as usize;
+    if end > store.len(area) {
+        return Err("Requested range is out of bounds".to_string());
+    }
+
+    match area {
+        DataArea::Coils => Ok(pack_bits(&store.coils.slice(start, end).unwrap_or_default())),
+        DataArea::DiscreteInputs => Ok(pack_bits(
+            &store.discrete_inputs.slice(start, end).unwrap_or_default(),
+        )),
+        DataArea::InputRegisters | DataArea::HoldingRegisters => {
+            Err("Packed snapshots are only available for bit areas".to_string())
+        }
+    }
+}
+
+/// All specs are read from one published view, so the result is coherent across areas.
+#[tauri::command]
+fn store_dump_window(
+    specs: Vec<WindowSpec>,
+    state: State<'_, AppState>,
+) -> Result<StoreWindow, String> {
+    let store = state.store.view();
+    let mut window = StoreWindow {
+        values: Vec::new(),
+        boundaries: Vec::with_capacity(specs.len()),
+    };
+    for spec in specs {
+        let start = state.store_offset(spec.offset)? as usize;
+        if start + spec.len as usize > store.len(spec.area) {
+            return Err("Requested range is out of bounds".to_string());
+        }
+        window.boundaries.push(window.values.len());
+        window
+            .values
+            .extend(store.values(spec.area, start, spec.len as usize));
+    }
+    Ok(window)
+}
+
+#[derive(Serialize, Clone)]
+struct ImportOutcome {
+    area: DataArea,
+    offset: u16,
+    len: u16,
+    error: Option<String>,
+}
+
+/// Copies each range in `specs` from a live device into the store. A failing range is reported
+/// in its outcome and the import continues with the next one; one client connection is shared
+/// by all ranges.
+#[tauri::command]
+async fn store_import_from_device(
+    addr: String,
+    unit: u8,
+    specs: Vec<WindowSpec>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ImportOutcome>, String> {
+    state.commands.record(
+        "store_import_from_device",
+        format!("{addr} unit {unit}, {} ranges", specs.len()),
+    );
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|err: std::net::AddrParseError| err.to_string())?;
+    let mut client = None;
+    let mut outcomes = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let result = import_range(&state, &mut client, addr, unit, &spec).await;
+        outcomes.push(ImportOutcome {
+            area: spec.area,
+            offset: spec.offset,
+            len: spec.len,
+            error: result.err(),
+        });
+    }
+    Ok(outcomes)
+}
+
+async fn import_range(
+    state: &AppState,
+    client: &mut Option<tokio_modbus::client::Context>,
+    addr: SocketAddr,
+    unit: u8,
+    spec: &WindowSpec,
+) -> Result<(), String> {
+    let area = spec.area;
+    state.ensure_locally_writable(area)?;
+    let start = state.store_offset(spec.offset)?;
+    if start as usize + spec.len as usize > state.store.view().len(area) {
+        return Err("Range is out of bounds".to_string());
+    }
+    let values = gateway::read_device_range(client, addr, unit, area, start, spec.len).await?;
+
+    let mut store = state
+        .store
+        .write()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    if start as usize + values.len() > store.len(area) {
+        return Err("Range is out of bounds".to_string());
+    }
+    store.write_values(area, start as usize, &values);
+    store.mark_initialized(area, start as usize, values.len());
+    emit_write(&state.app, &state.store, area, start, values);
+    Ok(())
 }
 
+/// Resolves with the value at `offset` as soon as it satisfies `condition`, re-checking after
+/// every store write; bit areas compare as 0/1. Fails once `timeout_ms` has passed.
 #[tauri::command]
-fn register_snapshot(
+async fn register_wait(
     area: DataArea,
     offset: u16,
-    len: u16,
+    condition: WaitCondition,
+    timeout_ms: u64,
     state: State<'_, AppState>,
-) -> Result<Vec<u16>, String> {
-    let store = state
-        .store
-        .read()
-        .map_err(|_| "Store lock poisoned".to_string())?;
-    let start = offset as usize;
-    let end = start + len as usize;
-    if end > STORE_SIZE {
-        return Err("Requested range is out of bounds".to_string());
+) -> Result<u16, String> {
+    let offset = state.store_offset(offset)? as usize;
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(timeout_ms);
+    let mut changes = state.store.subscribe();
+    loop {
+        let Some(value) = state.store.view().values(area, offset, 1).first().copied() else {
+            return Err("Offset is out of bounds".to_string());
+        };
+        if condition.matches(value) {
+            return Ok(value);
+        }
+        match tokio::time::timeout_at(deadline, changes.changed()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => return Err("Store is no longer available".to_string()),
+            Err(_) => {
+                return Err(format!(
+                    "Condition not met within {timeout_ms} ms (last value {value})"
+                ))
+            }
+        }
     }
-
-    let values = match area {
-        DataArea::Coils => store.coils[start..end]
-            .iter()
-            .map(|value| if *value { 1 } else { 0 })
-            .collect(),
-        DataArea::DiscreteInputs => store.discrete_inputs[start..end]
-            .iter()
-            .map(|value| if *value { 1 } else { 0 })
-            .collect(),
-        DataArea::InputRegisters => store.input_registers[start..end].to_vec(),
-        DataArea::HoldingRegisters => store.holding_registers[start..end].to_vec(),
-    };
-
-    Ok(values)
 }
 
 #[tauri::command]
@@ -250,30 +1188,35 @@ fn register_set(
     value: RegisterValue,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
+    state.commands.record("register_set", format!("{area:?} @{offset} = {}", value.as_u16()));
+    state.ensure_locally_writable(area)?;
+    state.check_value(area, &value)?;
+    let offset = state.store_offset(offset)?;
     let mut store = state
         .store
         .write()
         .map_err(|_| "Store lock poisoned".to_string())?;
     let index = offset as usize;
-    if index >= STORE_SIZE {
+    if index >= store.len(area) {
         return Err("Offset is out of bounds".to_string());
     }
 
     let bool_value = value.as_bool();
     let u16_value = value.as_u16();
 
+    store.mark_initialized(area, index, 1);
     match area {
         DataArea::Coils => {
-            store.coils[index] = bool_value;
+            store.coils.set(index, bool_value);
         }
         DataArea::DiscreteInputs => {
-            store.discrete_inputs[index] = bool_value;
+            store.discrete_inputs.set(index, bool_value);
         }
         DataArea::InputRegisters => {
-            store.input_registers[index] = u16_value;
+            store.input_registers.set(index, u16_value);
         }
         DataArea::HoldingRegisters => {
-            store.holding_registers[index] = u16_value;
+            store.holding_registers.set(index, u16_value);
         }
     }
 
@@ -283,7 +1226,7 @@ fn register_set(
         }
         DataArea::InputRegisters | DataArea::HoldingRegisters => u16_value,
     };
-    emit_update(&state.app, area, offset, vec![event_value]);
+    emit_write(&state.app, &state.store, area, offset, vec![event_value]);
 
     Ok(())
 }
@@ -295,6 +1238,13 @@ fn register_set_range(
     values: RegisterValues,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
+    state.commands.record(
+        "register_set_range",
+        format!("{area:?} @{offset}, {} values", values.len()),
+    );
+    state.ensure_locally_writable(area)?;
+    state.check_values(area, &values)?;
+    let offset = state.store_offset(offset)?;
     let mut store = state
         .store
         .write()
@@ -305,44 +1255,394 @@ fn register_set_range(
         DataArea::Coils => {
             let data = values.into_bools();
             let end = start + data.len();
-            if end > STORE_SIZE {
+            if end > store.len(area) {
                 return Err("Range is out of bounds".to_string());
             }
-            store.coils[start..end].copy_from_slice(&data);
-            emit_update(&state.app, area, offset, bools_to_u16(&data));
+            store.mark_initialized(area, start, data.len());
+            store.coils.write(start, &data);
+            emit_write(&state.app, &state.store, area, offset, bools_to_u16(&data));
         }
         DataArea::DiscreteInputs => {
             let data = values.into_bools();
             let end = start + data.len();
-            if end > STORE_SIZE {
+            if end > store.len(area) {
                 return Err("Range is out of bounds".to_string());
             }
-            store.discrete_inputs[start..end].copy_from_slice(&data);
-            emit_update(&state.app, area, offset, bools_to_u16(&data));
+            store.mark_initialized(area, start, data.len());
+            store.discrete_inputs.write(start, &data);
+            emit_write(&state.app, &state.store, area, offset, bools_to_u16(&data));
         }
         DataArea::InputRegisters => {
             let data = values.into_u16s();
             let end = start + data.len();
-            if end > STORE_SIZE {
+            if end > store.len(area) {
                 return Err("Range is out of bounds".to_string());
             }
-            store.input_registers[start..end].copy_from_slice(&data);
-            emit_update(&state.app, area, offset, data);
+            store.mark_initialized(area, start, data.len());
+            store.input_registers.write(start, &data);
+            emit_write(&state.app, &state.store, area, offset, data);
         }
         DataArea::HoldingRegisters => {
             let data = values.into_u16s();
             let end = start + data.len();
-            if end > STORE_SIZE {
+            if end > store.len(area) {
                 return Err("Range is out of bounds".to_string());
             }
-            store.holding_registers[start..end].copy_from_slice(&data);
-            emit_update(&state.app, area, offset, data);
+            store.mark_initialized(area, start, data.len());
+            store.holding_registers.write(start, &data);
+            emit_write(&state.app, &state.store, area, offset, data);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the whole store to the calling window as `modbus://snapshot`. Later changes reach
+/// every window through the broadcast `modbus://updated` events, so the window can apply
+/// those on top of this snapshot, using `revision` to discard anything older.
+#[tauri::command]
+fn store_subscribe(window: tauri::Window, state: State<'_, AppState>) -> Result<(), String> {
+    let store = state
+        .store
+        .read()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    let snapshot = StoreSnapshot::new(&store, state.store.revision());
+    state
+        .app
+        .emit_to(window.label(), "modbus://snapshot", snapshot)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn store_dirty(area: DataArea, state: State<'_, AppState>) -> Result<Vec<(u16, u16)>, String> {
+    let base = state.address_base()?;
+    let store = state
+        .store
+        .read()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    store
+        .dirty(area)
+        .into_iter()
+        .map(|(offset, value)| Ok((command_address(base, offset)?, value)))
+        .collect()
+}
+
+/// Addresses whose current value differs from the snapshot saved at `baseline_path`, with the
+/// saved and current values. Unlike `store_dirty`, which compares against the defaults, this
+/// checks a scenario's outcome against any earlier state. Reads the published store view.
+#[tauri::command]
+fn store_diff(baseline_path: String, state: State<'_, AppState>) -> Result<StoreDiff, String> {
+    let path = Path::new(&baseline_path);
+    let data =
+        paths::read_file(path)?.ok_or_else(|| format!("{} does not exist", path.display()))?;
+    let baseline: StoreBaseline = serde_json::from_slice(&data)
+        .map_err(|err| format!("Cannot parse {}: {err}", path.display()))?;
+    let base = state.address_base()?;
+    let view = state.store.view();
+    let diff = |area: DataArea, values: Option<RegisterValues>| {
+        let Some(values) = values else {
+            return Ok(Vec::new());
+        };
+        if values.len() > MAX_AREA_SIZE {
+            return Err(format!("{area:?} in the baseline exceeds {MAX_AREA_SIZE} values"));
+        }
+        let values = if is_bit_area(area) {
+            values.into_bools().into_iter().map(u16::from).collect()
+        } else {
+            values.into_u16s()
+        };
+        view.diff(area, &values)
+            .into_iter()
+            .map(|change| {
+                Ok(ValueChange {
+                    address: command_address(base, change.address)?,
+                    ..change
+                })
+            })
+            .collect()
+    };
+    Ok(StoreDiff {
+        coils: diff(DataArea::Coils, baseline.coils)?,
+        discrete_inputs: diff(DataArea::DiscreteInputs, baseline.discrete_inputs)?,
+        input_registers: diff(DataArea::InputRegisters, baseline.input_registers)?,
+        holding_registers: diff(DataArea::HoldingRegisters, baseline.holding_registers)?,
+    })
+}
+
+/// Current size of each area, taken from the live store so it reflects runtime resizes.
+#[tauri::command]
+fn store_info(state: State<'_, AppState>) -> Result<StoreInfo, String> {
+    let store = state
+        .store
+        .read()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    Ok(StoreInfo::from(&*store))
+}
+
+impl From<&ModbusStore> for StoreInfo {
+    fn from(store: &ModbusStore) -> Self {
+        StoreInfo {
+            coils: store.len(DataArea::Coils),
+            discrete_inputs: store.len(DataArea::DiscreteInputs),
+            input_registers: store.len(DataArea::InputRegisters),
+            holding_registers: store.len(DataArea::HoldingRegisters),
+            compact_areas: DataArea::ALL
+                .into_iter()
+                .filter(|area| store.is_compact(*area))
+                .collect(),
+            mapped_areas: DataArea::ALL
+                .into_iter()
+                .filter(|area| store.is_mapped(*area))
+                .collect(),
+        }
+    }
+}
+
+/// Unix time in milliseconds of the last write to each area, or `None` if never written.
+#[tauri::command]
+fn store_activity(state: State<'_, AppState>) -> StoreActivity {
+    let store = state.store.view();
+    StoreActivity {
+        coils: store.last_write_ms(DataArea::Coils),
+        discrete_inputs: store.last_write_ms(DataArea::DiscreteInputs),
+        input_registers: store.last_write_ms(DataArea::InputRegisters),
+        holding_registers: store.last_write_ms(DataArea::HoldingRegisters),
+    }
+}
+
+/// Replaces the register map read by `schema_read`. Offsets follow the address base in effect
+/// now and are kept as store offsets, so a later `address_base_set` does not move fields.
+#[tauri::command]
+fn schema_set(fields: Vec<SchemaField>, state: State<'_, AppState>) -> Result<(), String> {
+    state
+        .commands
+        .record("schema_set", format!("fields={}", fields.len()));
+    let fields = fields
+        .into_iter()
+        .map(|field| {
+            Ok(SchemaField {
+                offset: state.store_offset(field.offset)?,
+                ..field
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    schema::validate(&fields, &state.store.view())?;
+    *state
+        .schema
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())? = fields;
+    Ok(())
+}
+
+#[tauri::command]
+fn schema_read(state: State<'_, AppState>) -> Result<BTreeMap<String, serde_json::Value>, String> {
+    let fields = state
+        .schema
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())?
+        .clone();
+    schema::read(&fields, &state.store.view())
+}
+
+#[tauri::command]
+fn store_checksum(state: State<'_, AppState>) -> Result<StoreChecksum, String> {
+    let store = state
+        .store
+        .read()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    Ok(StoreChecksum {
+        revision: state.store.revision(),
+        checksum: format!("{:016x}", store.checksum()),
+    })
+}
+
+#[tauri::command]
+fn profile_save(
+    name: String,
+    config: ServerConfig,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state.commands.record("profile_save", name.as_str());
+    if name.trim().is_empty() {
+        return Err("Profile name must not be empty".to_string());
+    }
+    validate_config(&config)?;
+    ProfileStore::new(&state.app)?.save(&name, config)
+}
+
+#[tauri::command]
+fn profile_load(name: String, state: State<'_, AppState>) -> Result<ServerConfig, String> {
+    state.commands.record("profile_load", name.as_str());
+    ProfileStore::new(&state.app)?.load(&name)
+}
+
+#[tauri::command]
+fn profile_list(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    ProfileStore::new(&state.app)?.list()
+}
+
+/// Masters read `value` at this address until cleared; local snapshots keep showing the
+/// stored value and writes still update it underneath.
+#[tauri::command]
+fn read_override_set(
+    area: DataArea,
+    offset: u16,
+    value: RegisterValue,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state.commands.record("read_override_set", format!("{area:?} @{offset} = {}", value.as_u16()));
+    let offset = state.store_offset(offset)?;
+    if offset as usize >= state.store.view().len(area) {
+        return Err("Offset is out of bounds".to_string());
+    }
+    state.store.overrides().set(area, offset, value.as_u16());
+    Ok(())
+}
+
+#[tauri::command]
+fn read_override_clear(
+    area: DataArea,
+    offset: u16,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    state.commands.record("read_override_clear", format!("{area:?} @{offset}"));
+    let offset = state.store_offset(offset)?;
+    Ok(state.store.overrides().clear(area, offset))
+}
+
+/// Applies scattered single-address changes under one write lock, all or nothing, and emits
+/// one update per run of consecutive changed addresses. Returns the resulting revision.
+#[tauri::command]
+fn store_apply_patch(
+    patch: Vec<(DataArea, u16, RegisterValue)>,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    state.commands.record("store_apply_patch", format!("{} changes", patch.len()));
+    let patch = patch
+        .into_iter()
+        .map(|(area, offset, value)| {
+            state.ensure_locally_writable(area)?;
+            state.check_value(area, &value)?;
+            Ok((area, state.store_offset(offset)?, value))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut store = state
+        .store
+        .write()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    if patch
+        .iter()
+        .any(|(area, offset, _)| *offset as usize >= store.len(*area))
+    {
+        return Err("Offset is out of bounds".to_string());
+    }
+
+    let areas = [
+        DataArea::Coils,
+        DataArea::DiscreteInputs,
+        DataArea::InputRegisters,
+        DataArea::HoldingRegisters,
+    ];
+    for area in areas {
+        let changes: BTreeMap<u16, u16> = patch
+            .iter()
+            .filter(|(patch_area, _, _)| *patch_area == area)
+            .map(|(_, offset, value)| (*offset, value.as_u16()))
+            .collect();
+        for (&offset, &value) in &changes {
+            let index = offset as usize;
+            store.mark_initialized(area, index, 1);
+            store.write_values(area, index, &[value]);
+        }
+        for (start, values) in contiguous_runs(changes.into_iter().collect()) {
+            emit_write(&state.app, &state.store, area, start, values);
         }
     }
 
+    // An empty patch never writes, so releasing the guard leaves the revision as it is.
+    Ok(state.store.revision() + u64::from(!patch.is_empty()))
+}
+
+/// Replaces the whole store in one write-locked step, so masters see either the old or the new
+/// contents, then sends the new contents to every window as `modbus://snapshot`, bypassing the
+/// update queue. Returns the resulting revision.
+#[tauri::command]
+fn store_replace(contents: StoreContents, state: State<'_, AppState>) -> Result<u64, String> {
+    state.commands.record("store_replace", "");
+    let sizes = [
+        contents.coils.len(),
+        contents.discrete_inputs.len(),
+        contents.input_registers.len(),
+        contents.holding_registers.len(),
+    ];
+    if sizes.iter().any(|size| *size > MAX_AREA_SIZE) {
+        return Err(format!("Area size must not exceed {MAX_AREA_SIZE}"));
+    }
+
+    let mut store = state
+        .store
+        .write()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    store.replace(contents).map_err(|err| err.to_string())?;
+    // The revision is bumped once the guard is released; sent under it so no update overtakes.
+    let revision = state.store.revision() + 1;
+    let _ = state
+        .app
+        .emit("modbus://snapshot", StoreSnapshot::new(&store, revision));
+    Ok(revision)
+}
+
+/// Fills every area with deterministic demo data for `seed`, keeping the current area sizes,
+/// and sends the new contents to every window as `modbus://snapshot`. Returns the resulting
+/// revision.
+#[tauri::command]
+fn store_generate_demo(seed: u64, state: State<'_, AppState>) -> Result<u64, String> {
+    state.commands.record("store_generate_demo", format!("seed {seed}"));
+    let mut store = state
+        .store
+        .write()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    let sizes = [
+        store.len(DataArea::Coils),
+        store.len(DataArea::DiscreteInputs),
+        store.len(DataArea::InputRegisters),
+        store.len(DataArea::HoldingRegisters),
+    ];
+    store
+        .replace(demo::generate(seed, sizes))
+        .map_err(|err| err.to_string())?;
+    let revision = state.store.revision() + 1;
+    let _ = state
+        .app
+        .emit("modbus://snapshot", StoreSnapshot::new(&store, revision));
+    Ok(revision)
+}
+
+#[tauri::command]
+fn address_base_set(base: AddressBase, state: State<'_, AppState>) -> Result<(), String> {
+    state.commands.record("address_base_set", format!("{base:?}"));
+    *state
+        .address_base
+        .lock()
+        .map_err(|_| "State lock poisoned".to_string())? = base;
     Ok(())
 }
 
+#[tauri::command]
+fn store_resize(area: DataArea, size: usize, state: State<'_, AppState>) -> Result<usize, String> {
+    state.commands.record("store_resize", format!("{area:?} to {size}"));
+    if size > MAX_AREA_SIZE {
+        return Err(format!("Area size must not exceed {MAX_AREA_SIZE}"));
+    }
+
+    let mut store = state
+        .store
+        .write()
+        .map_err(|_| "Store lock poisoned".to_string())?;
+    store.resize(area, size).map_err(|err| err.to_string())?;
+    Ok(store.len(area))
+}
+
 impl RegisterValue {
     fn as_bool(&self) -> bool {
         match self {
@@ -357,9 +1657,30 @@ impl RegisterValue {
             RegisterValue::Number(value) => *value,
         }
     }
+
+    /// Rejects values that only fit `area` after coercion: numbers other than 0 and 1 for bit
+    /// areas, and booleans for register areas.
+    fn check_strict(&self, area: DataArea) -> Result<(), String> {
+        match (self, is_bit_area(area)) {
+            (RegisterValue::Number(value), true) if *value > 1 => {
+                Err(format!("{value} is not a bit value (expected 0 or 1)"))
+            }
+            (RegisterValue::Bool(_), false) => {
+                Err("Register areas take numbers, not booleans".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl RegisterValues {
+    fn len(&self) -> usize {
+        match self {
+            RegisterValues::Bools(values) => values.len(),
+            RegisterValues::Numbers(values) => values.len(),
+        }
+    }
+
     fn into_bools(self) -> Vec<bool> {
         match self {
             RegisterValues::Bools(values) => values,
@@ -373,26 +1694,109 @@ impl RegisterValues {
             RegisterValues::Numbers(values) => values,
         }
     }
+
+    fn check_strict(&self, area: DataArea) -> Result<(), String> {
+        match self {
+            RegisterValues::Bools(_) if !is_bit_area(area) => {
+                Err("Register areas take numbers, not booleans".to_string())
+            }
+            RegisterValues::Numbers(values) if is_bit_area(area) => {
+                match values.iter().find(|value| **value > 1) {
+                    Some(value) => Err(format!("{value} is not a bit value (expected 0 or 1)")),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_bit_area(area: DataArea) -> bool {
+    matches!(area, DataArea::Coils | DataArea::DiscreteInputs)
+}
+
+/// On Windows `SO_REUSEADDR` lets another socket steal an active port, so it is only the
+/// default elsewhere, where it just allows rebinding over connections lingering in TIME_WAIT.
+fn bind_listener(addr: SocketAddr, reuse_addr: bool) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(reuse_addr)?;
+    socket.bind(addr)?;
+    socket.listen(1024)
+}
+
+/// Port 0 is accepted and binds an OS-assigned ephemeral port; the port that was
+/// actually chosen is reported through `ServerStatus.bind` and `ServerStatus.binds`.
+fn validate_config(config: &ServerConfig) -> Result<(), String> {
+    if config.host.trim().is_empty() {
+        return Err("Host must not be empty".to_string());
+    }
+    if config.unit_id > MAX_UNIT_ID {
+        return Err(format!(
+            "Unit id {} is out of range (expected 0-{MAX_UNIT_ID}, 0 answers any unit)",
+            config.unit_id
+        ));
+    }
+    if config.options.max_connections == Some(0) {
+        return Err("max_connections must be at least 1".to_string());
+    }
+    if let Some(counter) = &config.options.read_counter {
+        if !matches!(counter.area, DataArea::InputRegisters | DataArea::HoldingRegisters) {
+            return Err("The read counter must be an input or holding register".to_string());
+        }
+    }
+    if config.options.mmap_path.is_some() {
+        if let Some((area, _)) = MAPPED_FILES
+            .iter()
+            .find(|(area, _)| config.options.compact_areas.contains(area))
+        {
+            return Err(format!("{area:?} cannot be both compact and memory-mapped"));
+        }
+    }
+    Ok(())
 }
 
 fn build_status(state: &ServerRuntimeState) -> ServerStatus {
     if let Some(runtime) = &state.runtime {
+        let degraded = state
+            .last_error_at
+            .is_some_and(|at| at.elapsed() < DEGRADED_WINDOW);
         ServerStatus {
             running: true,
+            health: if degraded { Health::Degraded } else { Health::Ok },
+            paused: runtime.controls.is_paused(),
             bind: runtime.bind.clone(),
-            connections: runtime.connections.load(Ordering::SeqCst),
+            binds: runtime.binds.clone(),
+            connections: runtime.connections.count(),
             last_error: state.last_error.clone(),
+            uptime_secs: runtime.started_at.elapsed().as_secs(),
+            started_at_ms: Some(runtime.started_at_ms),
         }
     } else {
         ServerStatus {
             running: false,
+            health: Health::Stopped,
+            paused: false,
             bind: String::new(),
+            binds: Vec::new(),
             connections: 0,
             last_error: state.last_error.clone(),
+            uptime_secs: 0,
+            started_at_ms: None,
         }
     }
 }
 
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
 fn build_menu<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<Menu<R>> {
     let menu = Menu::new(app)?;
 
@@ -439,18 +1843,42 @@ fn build_menu<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<Menu<R>> {
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
+/// Runs the app with the default hooks, which answer MEI type 13 (CANopen general reference) by
+/// echoing the request payload.
 pub fn run() {
+    run_with_hooks(ServiceHooks::default().with_mei_handler(
+        MEI_CANOPEN_GENERAL_REFERENCE,
+        Arc::new(mei::canopen_passthrough),
+    ));
+}
+
+/// Runs the app with `hooks` in place of the defaults, e.g. to answer MEI type 13 with a real
+/// CANopen handler. FC43 MEI types without a handler are refused with Illegal Function.
+pub fn run_with_hooks(hooks: ServiceHooks) {
+    let hooks = Arc::new(hooks);
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_opener::init())
-        .setup(|app| {
-            let store = Arc::new(RwLock::new(ModbusStore::new(STORE_SIZE)));
+        .setup(move |app| {
+            let store = Arc::new(SharedStore::new(ModbusStore::new(STORE_SIZE)));
             let server = Arc::new(Mutex::new(ServerRuntimeState::default()));
+            let metrics = Arc::new(ServerMetrics::default());
+            let updates = Arc::new(UpdateQueue::new(metrics.clone()));
+            updates.spawn_drain(app.handle().clone());
+            app.manage(updates.clone());
             app.manage(AppState {
                 app: app.handle().clone(),
                 store,
                 server,
+                metrics,
+                hooks,
+                logging: Arc::new(LogControl::init()),
+                address_base: Arc::default(),
+                updates,
+                strict_types: Arc::default(),
+                commands: Arc::default(),
+                schema: Arc::default(),
             });
             let menu = build_menu(app.handle())?;
             app.handle().set_menu(menu)?;
@@ -469,12 +1897,305 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             server_start,
+            server_ensure_started,
             server_stop,
             server_status,
+            take_last_error,
+            server_pause,
+            server_resume,
+            set_run_indicator,
+            server_metrics,
+            server_connections,
+            diagnostic_bundle,
+            benchmark,
+            exception_log,
+            command_log,
+            command_log_clear,
+            server_log_level,
+            server_freeze,
+            server_unfreeze,
             register_snapshot,
+            register_snapshot_packed,
+            store_dump_window,
             register_set,
-            register_set_range
+            register_wait,
+            register_set_range,
+            store_resize,
+            store_replace,
+            store_generate_demo,
+            store_import_from_device,
+            address_base_set,
+            store_apply_patch,
+            read_override_set,
+            read_override_clear,
+            store_info,
+            store_activity,
+            store_checksum,
+            schema_set,
+            schema_read,
+            store_dirty,
+            store_diff,
+            store_subscribe,
+            profile_save,
+            profile_load,
+            profile_list
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                let state = app.state::<AppState>();
+                if let Ok(server_state) = state.server.lock() {
+                    if let Some(runtime) = &server_state.runtime {
+                        runtime.controls.shut_down();
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::harness::{TempDir, TestServer};
+
+    fn config(value: serde_json::Value) -> ServerConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn running(config: ServerConfig, local_addr: SocketAddr) -> ServerRuntimeState {
+        ServerRuntimeState {
+            runtime: Some(RuntimeState {
+                cancel: CancellationToken::new(),
+                handle: tauri::async_runtime::spawn(async {}),
+                bind: local_addr.to_string(),
+                binds: vec![BindInfo::tcp(local_addr)],
+                config,
+                connections: ConnectionRegistry::default(),
+                controls: Arc::default(),
+                started_at: Instant::now(),
+                started_at_ms: unix_millis(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_host_is_rejected() {
+        let blank = config(json!({ "host": "  ", "port": 502, "unit_id": 1 }));
+        assert_eq!(
+            validate_config(&blank).unwrap_err(),
+            "Host must not be empty"
+        );
+    }
+
+    #[tokio::test]
+    async fn port_zero_reports_the_assigned_port() {
+        let zero = config(json!({ "host": "127.0.0.1", "port": 0, "unit_id": 1 }));
+        validate_config(&zero).unwrap();
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), true).unwrap();
+        let local_addr = listener.local_addr().unwrap();
+        assert_ne!(local_addr.port(), 0);
+        let status = build_status(&running(zero, local_addr));
+        assert_eq!(status.bind, local_addr.to_string());
+        assert_eq!(status.binds[0].addr, local_addr.to_string());
+    }
+
+    #[tokio::test]
+    async fn rebinds_the_same_port_after_a_stop() {
+        use tokio_modbus::prelude::*;
+
+        let mut addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut store = Arc::new(SharedStore::new(ModbusStore::new(8)));
+        for _ in 0..5 {
+            // The previous round's connection may still be open or in TIME_WAIT on this port.
+            let listener = bind_listener(addr, true).unwrap();
+            addr = listener.local_addr().unwrap();
+            let server = TestServer::serve(
+                listener,
+                store,
+                1,
+                ServiceOptions::default(),
+                ServiceHooks::default(),
+            );
+            let mut client = server.client(1).await;
+            client.read_holding_registers(0, 1).await.unwrap().unwrap();
+            store = server.stop().await;
+            drop(client);
+        }
+    }
+
+    #[test]
+    fn one_based_addresses_map_to_the_offset_below() {
+        assert_eq!(store_offset(AddressBase::ZeroBased, 0), Ok(0));
+        assert_eq!(store_offset(AddressBase::ZeroBased, u16::MAX), Ok(u16::MAX));
+        assert_eq!(store_offset(AddressBase::OneBased, 1), Ok(0));
+        assert_eq!(
+            store_offset(AddressBase::OneBased, u16::MAX),
+            Ok(u16::MAX - 1)
+        );
+        assert!(store_offset(AddressBase::OneBased, 0).is_err());
+        // 65536, one past the last one-based address, does not reach a command at all.
+        assert!(serde_json::from_value::<u16>(json!(65536)).is_err());
+    }
+
+    #[test]
+    fn store_offsets_map_back_to_the_address_above() {
+        assert_eq!(command_address(AddressBase::ZeroBased, 0), Ok(0));
+        assert_eq!(
+            command_address(AddressBase::ZeroBased, u16::MAX),
+            Ok(u16::MAX)
+        );
+        assert_eq!(command_address(AddressBase::OneBased, 0), Ok(1));
+        assert_eq!(
+            command_address(AddressBase::OneBased, u16::MAX - 1),
+            Ok(u16::MAX)
+        );
+        assert!(command_address(AddressBase::OneBased, u16::MAX).is_err());
+        for address in [1, 2, u16::MAX] {
+            let offset = store_offset(AddressBase::OneBased, address).unwrap();
+            assert_eq!(command_address(AddressBase::OneBased, offset), Ok(address));
+        }
+    }
+
+    #[test]
+    fn store_info_follows_resize_and_replace() {
+        let mut store = ModbusStore::new(8);
+        store.set_compact(DataArea::Coils, true);
+        store.resize(DataArea::HoldingRegisters, 100).unwrap();
+        let info = StoreInfo::from(&store);
+        assert_eq!((info.coils, info.holding_registers), (8, 100));
+        assert_eq!(info.compact_areas, vec![DataArea::Coils]);
+
+        store
+            .replace(StoreContents {
+                coils: vec![true; 3],
+                discrete_inputs: vec![false; 4],
+                input_registers: vec![0; 5],
+                holding_registers: vec![1; 6],
+            })
+            .unwrap();
+        let info = StoreInfo::from(&store);
+        let sizes = (
+            info.coils,
+            info.discrete_inputs,
+            info.input_registers,
+            info.holding_registers,
+        );
+        assert_eq!(sizes, (3, 4, 5, 6));
+        assert_eq!(info.compact_areas, vec![DataArea::Coils]);
+        assert!(info.mapped_areas.is_empty());
+    }
+
+    #[test]
+    fn unit_id_must_not_be_reserved() {
+        for (unit_id, valid) in [
+            (0, true),
+            (1, true),
+            (247, true),
+            (248, false),
+            (255, false),
+        ] {
+            let unit = config(json!({ "host": "127.0.0.1", "port": 502, "unit_id": unit_id }));
+            assert_eq!(validate_config(&unit).is_ok(), valid, "unit id {unit_id}");
+        }
+    }
+
+    #[test]
+    fn unit_id_accepts_its_aliases() {
+        for key in ["unit_id", "device_address", "slave_id"] {
+            let unit = config(json!({ "host": "127.0.0.1", "port": 502, (key): 17 }));
+            assert_eq!(unit.unit_id, 17, "{key}");
+        }
+        let value = serde_json::to_value(config(json!({ "host": "h", "port": 1, "slave_id": 3 })));
+        assert_eq!(value.unwrap()["unit_id"], 3);
+    }
+
+    #[test]
+    fn strict_types_reject_coerced_values() {
+        let value = |json| serde_json::from_value::<RegisterValue>(json).unwrap();
+        assert!(value(json!(1)).check_strict(DataArea::Coils).is_ok());
+        assert!(value(json!(2)).check_strict(DataArea::Coils).is_err());
+        assert!(value(json!(true)).check_strict(DataArea::Coils).is_ok());
+        assert!(value(json!(true))
+            .check_strict(DataArea::HoldingRegisters)
+            .is_err());
+        assert!(value(json!(500))
+            .check_strict(DataArea::InputRegisters)
+            .is_ok());
+
+        let values = |json| serde_json::from_value::<RegisterValues>(json).unwrap();
+        let coils = values(json!([0, 1, 7]));
+        let err = coils.check_strict(DataArea::DiscreteInputs).unwrap_err();
+        assert_eq!(err, "7 is not a bit value (expected 0 or 1)");
+        assert!(values(json!([0, 1])).check_strict(DataArea::Coils).is_ok());
+        let bools = values(json!([true, false]));
+        assert!(bools.check_strict(DataArea::HoldingRegisters).is_err());
+        assert!(values(json!([2, 3]))
+            .check_strict(DataArea::HoldingRegisters)
+            .is_ok());
+    }
+
+    #[test]
+    fn take_error_reads_and_clears_once() {
+        let mut server_state = ServerRuntimeState::default();
+        server_state.set_error("bind failed".to_string());
+        assert_eq!(server_state.take_error().as_deref(), Some("bind failed"));
+        assert!(server_state.last_error_at.is_none());
+        assert_eq!(server_state.take_error(), None);
+    }
+
+    #[test]
+    fn errors_recorded_while_taking_are_taken_exactly_once() {
+        let server = Arc::new(Mutex::new(ServerRuntimeState::default()));
+        let recorder = {
+            let server = server.clone();
+            std::thread::spawn(move || {
+                for index in 0..1000 {
+                    server.lock().unwrap().set_error(index.to_string());
+                }
+            })
+        };
+        let mut taken = Vec::new();
+        while !recorder.is_finished() {
+            taken.extend(server.lock().unwrap().take_error());
+        }
+        recorder.join().unwrap();
+        taken.extend(server.lock().unwrap().take_error());
+        let taken: Vec<u32> = taken.iter().map(|error| error.parse().unwrap()).collect();
+        assert!(taken.windows(2).all(|pair| pair[0] < pair[1]), "{taken:?}");
+        assert_eq!(taken.last(), Some(&999));
+    }
+
+    #[test]
+    fn zero_max_connections_is_rejected() {
+        let zero = json!({ "host": "127.0.0.1", "port": 502, "unit_id": 1, "max_connections": 0 });
+        assert!(validate_config(&config(zero)).is_err());
+        let one = json!({ "host": "127.0.0.1", "port": 502, "unit_id": 1, "max_connections": 1 });
+        assert!(validate_config(&config(one)).is_ok());
+    }
+
+    #[test]
+    fn start_slot_is_released_unless_filled() {
+        let starting = config(json!({ "host": "127.0.0.1", "port": 502, "unit_id": 1 }));
+        let server = Mutex::new(ServerRuntimeState {
+            starting: Some(starting),
+            ..Default::default()
+        });
+        drop(StartSlot { server: &server });
+        assert!(server.lock().unwrap().starting.is_none());
+    }
+
+    #[test]
+    fn failed_mapping_leaves_earlier_files_unused() {
+        let dir = TempDir::new();
+        std::fs::create_dir(dir.path().join("holding_registers.u16le")).unwrap();
+        let store = ModbusStore::new(4);
+        let err = open_mappings(&store, dir.path()).unwrap_err();
+        assert!(err.contains("holding_registers.u16le"), "{err}");
+        let first = dir.path().join("input_registers.u16le");
+        assert_eq!(std::fs::metadata(first).unwrap().len(), 0);
+    }
 }
@@ -0,0 +1,30 @@
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+/// Handle to the global subscriber's level filter. Logging starts disabled, so request and
+/// connection spans cost a single filter check until a level is set.
+pub struct LogControl {
+    filter: reload::Handle<LevelFilter, Registry>,
+}
+
+impl LogControl {
+    pub fn init() -> Self {
+        let (filter, handle) = reload::Layer::new(LevelFilter::OFF);
+        let _ = tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt::layer())
+            .try_init();
+        Self { filter: handle }
+    }
+
+    pub fn set_level(&self, level: &str) -> Result<(), String> {
+        let level: LevelFilter = level
+            .parse()
+            .map_err(|_| format!("Unknown log level \"{level}\""))?;
+        self.filter
+            .reload(level)
+            .map_err(|err| err.to_string())
+    }
+}
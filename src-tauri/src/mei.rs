@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio_modbus::ExceptionCode;
+
+pub const ENCAPSULATED_INTERFACE_TRANSPORT: u8 = 0x2B;
+pub const MEI_CANOPEN_GENERAL_REFERENCE: u8 = 0x0D;
+
+pub type MeiHandler = Arc<dyn Fn(&[u8]) -> Result<Vec<u8>, ExceptionCode> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct MeiHandlers {
+    handlers: HashMap<u8, MeiHandler>,
+}
+
+impl MeiHandlers {
+    pub fn with_handler(mut self, mei_type: u8, handler: MeiHandler) -> Self {
+        self.handlers.insert(mei_type, handler);
+        self
+    }
+
+    pub fn dispatch(&self, mei_type: u8, payload: &[u8]) -> Result<Vec<u8>, ExceptionCode> {
+        let handler = self
+            .handlers
+            .get(&mei_type)
+            .ok_or(ExceptionCode::IllegalFunction)?;
+        handler(payload)
+    }
+}
+
+pub fn canopen_passthrough(payload: &[u8]) -> Result<Vec<u8>, ExceptionCode> {
+    Ok(payload.to_vec())
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::exception_log::ExceptionLog;
+use crate::latency::{LatencyHistograms, LatencyStats};
+
+#[derive(Default)]
+pub struct TrafficCounters {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl TrafficCounters {
+    pub fn record_in(&self, len: usize) {
+        self.bytes_in.fetch_add(len as u64, Ordering::SeqCst);
+    }
+
+    pub fn record_out(&self, len: usize) {
+        self.bytes_out.fetch_add(len as u64, Ordering::SeqCst);
+    }
+
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::SeqCst)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::SeqCst)
+    }
+
+    fn reset(&self) {
+        self.bytes_in.store(0, Ordering::SeqCst);
+        self.bytes_out.store(0, Ordering::SeqCst);
+    }
+}
+
+#[derive(Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheCounters {
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::SeqCst),
+            misses: self.misses.load(Ordering::SeqCst),
+        }
+    }
+
+    fn reset(&self) {
+        self.hits.store(0, Ordering::SeqCst);
+        self.misses.store(0, Ordering::SeqCst);
+    }
+}
+
+#[derive(Default)]
+pub struct ServerMetrics {
+    pub traffic: TrafficCounters,
+    pub upstream_cache: CacheCounters,
+    pub exceptions: ExceptionLog,
+    pub latency: LatencyHistograms,
+    dropped_updates: AtomicU64,
+    read_timeouts: AtomicU64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MetricsSnapshot {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub cache: CacheStats,
+    pub dropped_updates: u64,
+    /// Connections closed by `read_timeout_ms` with a frame left incomplete.
+    pub read_timeouts: u64,
+    pub latency: Vec<LatencyStats>,
+}
+
+impl ServerMetrics {
+    pub fn reset(&self) {
+        self.traffic.reset();
+        self.upstream_cache.reset();
+        self.exceptions.clear();
+        self.latency.clear();
+        self.dropped_updates.store(0, Ordering::SeqCst);
+        self.read_timeouts.store(0, Ordering::SeqCst);
+    }
+
+    pub fn record_dropped_update(&self) {
+        self.dropped_updates.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn record_read_timeout(&self) {
+        self.read_timeouts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            bytes_in: self.traffic.bytes_in(),
+            bytes_out: self.traffic.bytes_out(),
+            cache: self.upstream_cache.stats(),
+            dropped_updates: self.dropped_updates.load(Ordering::SeqCst),
+            read_timeouts: self.read_timeouts.load(Ordering::SeqCst),
+            latency: self.latency.stats(),
+        }
+    }
+}
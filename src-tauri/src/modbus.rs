@@ -1,35 +1,318 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter};
+use tokio::sync::OwnedSemaphorePermit;
 use tokio_modbus::server::Service;
 use tokio_modbus::{ExceptionCode, Request, Response, SlaveRequest};
+use tracing::{debug, debug_span, field, Instrument, Span};
+
+use crate::activity::{ActivityLog, LogLevel};
+use crate::area::{Area, Cell, Mapping};
+use crate::checksum::Fnv1a64;
+use crate::commands::{self, CommandAction, CommandRegister};
+use crate::connections::{ConnectionEntry, ConnectionRegistry};
+use crate::exception_log::ExceptionRecord;
+use crate::exceptions::ExceptionPolicy;
+use crate::freeze::{BufferedWrite, FreezeWritePolicy};
+use crate::gateway::UpstreamPool;
+use crate::hooks::{ServiceHooks, WriteEvent};
+use crate::mei::ENCAPSULATED_INTERFACE_TRANSPORT;
+use crate::metrics::ServerMetrics;
+use crate::overrides::ReadCounter;
+use crate::quirks::ResponseQuirks;
+use crate::rng::SplitMix64;
+use crate::sink::UpdateSink;
+use crate::store::SharedStore;
+use crate::transport::ProtocolIdMode;
+use crate::unix_millis;
+use crate::word_order::{FloatPair, WordOrder};
 
 pub const STORE_SIZE: usize = 1000;
+pub const MAX_AREA_SIZE: usize = u16::MAX as usize + 1;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ModbusStore {
+    pub coils: Area<bool>,
+    pub discrete_inputs: Area<bool>,
+    pub input_registers: Area<u16>,
+    pub holding_registers: Area<u16>,
+    last_write_ms: [Option<u64>; 4],
+    defaults: StoreDefaults,
+}
+
+/// Complete contents for every area, as accepted by `store_replace`.
+#[derive(Debug, Deserialize)]
+pub struct StoreContents {
     pub coils: Vec<bool>,
     pub discrete_inputs: Vec<bool>,
     pub input_registers: Vec<u16>,
     pub holding_registers: Vec<u16>,
 }
 
+#[derive(Clone, Debug, Serialize)]
+pub struct ValueChange {
+    pub address: u16,
+    pub old: Option<u16>,
+    pub new: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StoreDefaults {
+    pub coil: bool,
+    pub register: u16,
+}
+
 impl ModbusStore {
     pub fn new(size: usize) -> Self {
+        Self::with_defaults(size, StoreDefaults::default())
+    }
+
+    pub fn with_defaults(size: usize, defaults: StoreDefaults) -> Self {
         Self {
-            coils: vec![false; size],
-            discrete_inputs: vec![false; size],
-            input_registers: vec![0; size],
-            holding_registers: vec![0; size],
+            coils: Area::new(size, defaults.coil),
+            discrete_inputs: Area::new(size, defaults.coil),
+            input_registers: Area::new(size, defaults.register),
+            holding_registers: Area::new(size, defaults.register),
+            last_write_ms: [None; 4],
+            defaults,
+        }
+    }
+
+    /// Switches the fill values used for growing areas and refills every address that has
+    /// not been written yet, leaving written data untouched.
+    pub fn set_defaults(&mut self, defaults: StoreDefaults) {
+        self.defaults = defaults;
+        self.coils.set_fill(defaults.coil);
+        self.discrete_inputs.set_fill(defaults.coil);
+        self.input_registers.set_fill(defaults.register);
+        self.holding_registers.set_fill(defaults.register);
+    }
+
+    /// Moves `area` between a vector and a map of written addresses, keeping its contents.
+    pub fn set_compact(&mut self, area: DataArea, compact: bool) {
+        let StoreDefaults { coil, register } = self.defaults;
+        match area {
+            DataArea::Coils => self.coils.set_compact(compact, coil),
+            DataArea::DiscreteInputs => self.discrete_inputs.set_compact(compact, coil),
+            DataArea::InputRegisters => self.input_registers.set_compact(compact, register),
+            DataArea::HoldingRegisters => self.holding_registers.set_compact(compact, register),
+        }
+    }
+
+    pub fn is_compact(&self, area: DataArea) -> bool {
+        match area {
+            DataArea::Coils => self.coils.is_compact(),
+            DataArea::DiscreteInputs => self.discrete_inputs.is_compact(),
+            DataArea::InputRegisters => self.input_registers.is_compact(),
+            DataArea::HoldingRegisters => self.holding_registers.is_compact(),
+        }
+    }
+
+    /// Called for every write to the store, so it also stamps the area's last-write time.
+    pub fn mark_initialized(&mut self, area: DataArea, start: usize, len: usize) {
+        self.last_write_ms[area.index()] = Some(unix_millis());
+        match area {
+            DataArea::Coils => self.coils.mark_written(start, len),
+            DataArea::DiscreteInputs => self.discrete_inputs.mark_written(start, len),
+            DataArea::InputRegisters => self.input_registers.mark_written(start, len),
+            DataArea::HoldingRegisters => self.holding_registers.mark_written(start, len),
+        }
+    }
+
+    pub fn is_initialized(&self, area: DataArea, start: usize, len: usize) -> bool {
+        match area {
+            DataArea::Coils => self.coils.is_written(start, len),
+            DataArea::DiscreteInputs => self.discrete_inputs.is_written(start, len),
+            DataArea::InputRegisters => self.input_registers.is_written(start, len),
+            DataArea::HoldingRegisters => self.holding_registers.is_written(start, len),
+        }
+    }
+
+    /// Refills every area with the current defaults and forgets which addresses were written.
+    pub fn reset(&mut self) {
+        self.coils.reset(self.defaults.coil);
+        self.discrete_inputs.reset(self.defaults.coil);
+        self.input_registers.reset(self.defaults.register);
+        self.holding_registers.reset(self.defaults.register);
+    }
+
+    /// Swaps in `contents` wholesale, resizing areas to match. Every address counts as written
+    /// afterwards; the configured defaults, compact and mapped areas are kept.
+    pub fn replace(&mut self, contents: StoreContents) -> io::Result<()> {
+        let StoreDefaults { coil, register } = self.defaults;
+        self.coils.replace(contents.coils, coil)?;
+        self.discrete_inputs.replace(contents.discrete_inputs, coil)?;
+        self.input_registers.replace(contents.input_registers, register)?;
+        self.holding_registers.replace(contents.holding_registers, register)?;
+        self.last_write_ms = [Some(unix_millis()); 4];
+        Ok(())
+    }
+
+    /// Takes the contents of `other`, typically the frozen copy, keeping mapped areas mapped.
+    pub fn restore(&mut self, other: &ModbusStore) -> io::Result<()> {
+        let StoreDefaults { coil, register } = self.defaults;
+        self.coils.restore(&other.coils, coil)?;
+        self.discrete_inputs.restore(&other.discrete_inputs, coil)?;
+        self.input_registers.restore(&other.input_registers, register)?;
+        self.holding_registers.restore(&other.holding_registers, register)?;
+        self.last_write_ms = other.last_write_ms;
+        Ok(())
+    }
+
+    /// Opens the file at `path` to back `area`; see `Area::open_mapping` for the layout.
+    /// Registers are stored little-endian, the same byte order `checksum` uses.
+    pub fn open_mapping(&self, area: DataArea, path: &Path) -> io::Result<Mapping> {
+        match area {
+            DataArea::Coils => self.coils.open_mapping(path),
+            DataArea::DiscreteInputs => self.discrete_inputs.open_mapping(path),
+            DataArea::InputRegisters => self.input_registers.open_mapping(path),
+            DataArea::HoldingRegisters => self.holding_registers.open_mapping(path),
+        }
+    }
+
+    /// Backs `area` with a mapping opened for it by `open_mapping`.
+    pub fn map(&mut self, area: DataArea, mapping: Mapping) {
+        match area {
+            DataArea::Coils => self.coils.map(mapping),
+            DataArea::DiscreteInputs => self.discrete_inputs.map(mapping),
+            DataArea::InputRegisters => self.input_registers.map(mapping),
+            DataArea::HoldingRegisters => self.holding_registers.map(mapping),
+        }
+    }
+
+    pub fn unmap(&mut self, area: DataArea) {
+        match area {
+            DataArea::Coils => self.coils.unmap(),
+            DataArea::DiscreteInputs => self.discrete_inputs.unmap(),
+            DataArea::InputRegisters => self.input_registers.unmap(),
+            DataArea::HoldingRegisters => self.holding_registers.unmap(),
+        }
+    }
+
+    pub fn is_mapped(&self, area: DataArea) -> bool {
+        match area {
+            DataArea::Coils => self.coils.is_mapped(),
+            DataArea::DiscreteInputs => self.discrete_inputs.is_mapped(),
+            DataArea::InputRegisters => self.input_registers.is_mapped(),
+            DataArea::HoldingRegisters => self.holding_registers.is_mapped(),
+        }
+    }
+
+    /// Unix time in milliseconds of the last write to `area`, from masters or local commands.
+    pub fn last_write_ms(&self, area: DataArea) -> Option<u64> {
+        self.last_write_ms[area.index()]
+    }
+
+    /// Addresses in `area` whose value differs from the configured default fill value.
+    pub fn dirty(&self, area: DataArea) -> Vec<(u16, u16)> {
+        let default = match area {
+            DataArea::Coils | DataArea::DiscreteInputs => self.defaults.coil as u16,
+            DataArea::InputRegisters | DataArea::HoldingRegisters => self.defaults.register,
+        };
+        self.values(area, 0, self.len(area))
+            .into_iter()
+            .enumerate()
+            .filter(|(_, value)| *value != default)
+            .map(|(index, value)| (index as u16, value))
+            .collect()
+    }
+
+    /// Addresses in `area` whose value differs from `baseline`, which may be shorter or longer
+    /// than the area; an address on only one side has no value on the other.
+    pub fn diff(&self, area: DataArea, baseline: &[u16]) -> Vec<ValueChange> {
+        let current = self.values(area, 0, self.len(area));
+        (0..current.len().max(baseline.len()))
+            .filter_map(|index| {
+                let old = baseline.get(index).copied();
+                let new = current.get(index).copied();
+                (old != new).then_some(ValueChange {
+                    address: index as u16,
+                    old,
+                    new,
+                })
+            })
+            .collect()
+    }
+
+    /// Values of `len` addresses from `start`, with bits as 0/1; empty if out of range.
+    pub fn values(&self, area: DataArea, start: usize, len: usize) -> Vec<u16> {
+        let end = start + len;
+        match area {
+            DataArea::Coils => self.coils.slice(start, end).map(|bits| bools_to_u16(&bits)),
+            DataArea::DiscreteInputs => self
+                .discrete_inputs
+                .slice(start, end)
+                .map(|bits| bools_to_u16(&bits)),
+            DataArea::InputRegisters => self.input_registers.slice(start, end),
+            DataArea::HoldingRegisters => self.holding_registers.slice(start, end),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Writes `values` from `start`, setting bits for any nonzero value; nothing is written if
+    /// the range does not fit.
+    pub fn write_values(&mut self, area: DataArea, start: usize, values: &[u16]) -> bool {
+        let bits = || values.iter().map(|value| *value != 0).collect::<Vec<_>>();
+        match area {
+            DataArea::Coils => self.coils.write(start, &bits()),
+            DataArea::DiscreteInputs => self.discrete_inputs.write(start, &bits()),
+            DataArea::InputRegisters => self.input_registers.write(start, values),
+            DataArea::HoldingRegisters => self.holding_registers.write(start, values),
+        }
+    }
+
+    pub fn len(&self, area: DataArea) -> usize {
+        match area {
+            DataArea::Coils => self.coils.len(),
+            DataArea::DiscreteInputs => self.discrete_inputs.len(),
+            DataArea::InputRegisters => self.input_registers.len(),
+            DataArea::HoldingRegisters => self.holding_registers.len(),
+        }
+    }
+
+    /// Fails only for a mapped area whose file cannot be resized or remapped.
+    pub fn resize(&mut self, area: DataArea, size: usize) -> io::Result<()> {
+        let StoreDefaults { coil, register } = self.defaults;
+        match area {
+            DataArea::Coils => self.coils.resize(size, coil),
+            DataArea::DiscreteInputs => self.discrete_inputs.resize(size, coil),
+            DataArea::InputRegisters => self.input_registers.resize(size, register),
+            DataArea::HoldingRegisters => self.holding_registers.resize(size, register),
+        }
+    }
+
+    /// Hashes the length and contents of every area in a fixed order, with registers fed in
+    /// little-endian so the result is identical across platforms.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = Fnv1a64::default();
+        for bits in [&self.coils, &self.discrete_inputs] {
+            hasher.write_u64(bits.len() as u64);
+            for value in bits.iter() {
+                hasher.write(&[value as u8]);
+            }
+        }
+        for words in [&self.input_registers, &self.holding_registers] {
+            hasher.write_u64(words.len() as u64);
+            for value in words.iter() {
+                hasher.write_u16(value);
+            }
         }
+        hasher.finish()
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum DataArea {
     #[serde(rename = "coils")]
     Coils,
@@ -41,47 +324,328 @@ pub enum DataArea {
     HoldingRegisters,
 }
 
+impl DataArea {
+    pub const ALL: [DataArea; 4] = [
+        DataArea::Coils,
+        DataArea::DiscreteInputs,
+        DataArea::InputRegisters,
+        DataArea::HoldingRegisters,
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            DataArea::Coils => 0,
+            DataArea::DiscreteInputs => 1,
+            DataArea::InputRegisters => 2,
+            DataArea::HoldingRegisters => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionLimitMode {
+    #[default]
+    Reject,
+    Queue,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServiceOptions {
+    pub strict_coil_packing: bool,
+    pub drop_probability: f32,
+    pub drop_seed: Option<u64>,
+    pub lenient_reads: bool,
+    pub snapshot_reads: bool,
+    pub max_requests_per_connection: Option<u64>,
+    pub max_connections: Option<usize>,
+    pub connection_limit_mode: ConnectionLimitMode,
+    pub sparse: bool,
+    pub exceptions: ExceptionPolicy,
+    /// Emits `modbus://scan_detected` once a connection has addressed more distinct unit ids
+    /// than this, whether or not those ids are listed in `track_units`.
+    pub scan_unit_threshold: Option<usize>,
+    /// When set, only these unit ids get their own entry in `server_connections()`; requests
+    /// for any other id are counted together as `other_unit_requests`.
+    pub track_units: Option<Vec<u8>>,
+    /// Holding register pairs that hold an `f32`, decoded in `float_word_order` unless the
+    /// pair carries its own order.
+    pub float_pairs: Vec<FloatPair>,
+    pub float_word_order: WordOrder,
+    /// Emulates device boot time: every request is answered `ServerDeviceBusy` until this long
+    /// after the server started. Connections are accepted throughout.
+    pub startup_busy_ms: Option<u64>,
+    /// Minimum interval between update events for the same address; takes precedence over
+    /// `diff_updates`.
+    pub debounce_ms: Option<u64>,
+    pub response_quirks: ResponseQuirks,
+    pub update_queue_capacity: Option<usize>,
+    pub lock_read_only_areas: bool,
+    /// Makes the local set commands reject values that only fit the area after coercion,
+    /// such as `2` for a coil or `true` for a register.
+    pub strict_types: bool,
+    /// Fixed FC17 run indicator. When unset it follows the server state, ON unless paused;
+    /// `set_run_indicator` overrides either at runtime.
+    pub run_indicator: Option<bool>,
+    /// Unit id written into every response MBAP header instead of echoing the request's. This
+    /// is applied after routing: with `unit_id` 0 or `upstreams`, requests are still answered
+    /// per requested unit, but masters can no longer tell from the reply which unit answered.
+    pub response_unit_id: Option<u8>,
+    /// Requests with a non-zero MBAP protocol identifier close the connection unless this is
+    /// `echo`.
+    pub protocol_id_mode: ProtocolIdMode,
+    /// Closes a connection that leaves a request incomplete for this long, counted from its
+    /// first byte. Unlike an idle timeout it never fires between complete requests.
+    pub read_timeout_ms: Option<u64>,
+    pub read_counter: Option<ReadCounter>,
+    /// Areas kept as a map of written addresses instead of a vector, for large address spaces
+    /// that are only sparsely used. Unwritten addresses read as the default value.
+    pub compact_areas: Vec<DataArea>,
+    /// Directory whose `input_registers.u16le` and `holding_registers.u16le` files back those
+    /// areas through a shared memory mapping, so other processes can read and write them. Each
+    /// register is two bytes, little-endian, at byte offset `2 * address`. An existing file
+    /// sets the area's size and contents; a missing or empty one is created from the store.
+    pub mmap_path: Option<String>,
+    pub coil_default: bool,
+    pub register_default: u16,
+    pub diff_updates: bool,
+    /// Device-specific caps on a single read, below the spec's 2000 bits and 125 registers.
+    pub max_read_coils: Option<u16>,
+    pub max_read_registers: Option<u16>,
+    /// Unit ids answered by forwarding to a real device instead of the local store. Forwarded
+    /// requests skip everything that concerns the local device: `startup_busy_ms`, `areas`,
+    /// freezing and the deny events, so the upstream device answers them as it would directly.
+    pub upstreams: HashMap<u8, SocketAddr>,
+    pub upstream_cache_ttl_ms: Option<u64>,
+    /// What master writes do while the store is frozen, unless the freeze picks its own.
+    pub freeze_write_policy: FreezeWritePolicy,
+    /// Matched only for master writes applied to the live store, so while the store is frozen
+    /// with `Reject` or `Buffer` no command register fires, including one bound to `unfreeze`.
+    pub command_registers: Vec<CommandRegister>,
+    pub log_level: LogLevel,
+    #[serde(flatten)]
+    pub areas: AreaPresence,
+}
+
+/// Which areas the emulated device implements. Requests for an absent area are refused even
+/// though their function code is otherwise supported.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AreaPresence {
+    pub coils_enabled: bool,
+    pub discrete_enabled: bool,
+    pub input_regs_enabled: bool,
+    pub holding_regs_enabled: bool,
+}
+
+impl Default for AreaPresence {
+    fn default() -> Self {
+        Self {
+            coils_enabled: true,
+            discrete_enabled: true,
+            input_regs_enabled: true,
+            holding_regs_enabled: true,
+        }
+    }
+}
+
+impl AreaPresence {
+    pub fn is_enabled(&self, area: DataArea) -> bool {
+        match area {
+            DataArea::Coils => self.coils_enabled,
+            DataArea::DiscreteInputs => self.discrete_enabled,
+            DataArea::InputRegisters => self.input_regs_enabled,
+            DataArea::HoldingRegisters => self.holding_regs_enabled,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ServerControls {
+    paused: AtomicBool,
+    shutting_down: AtomicBool,
+    /// Runtime override of the FC17 run indicator: 0 = none, 1 = off, 2 = on.
+    run_indicator: AtomicU8,
+}
+
+impl ServerControls {
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn shut_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn set_run_indicator(&self, on: Option<bool>) {
+        let encoded = match on {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        };
+        self.run_indicator.store(encoded, Ordering::SeqCst);
+    }
+
+    pub fn run_indicator(&self) -> Option<bool> {
+        match self.run_indicator.load(Ordering::SeqCst) {
+            1 => Some(false),
+            2 => Some(true),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ModbusService {
-    store: Arc<RwLock<ModbusStore>>,
-    app: AppHandle,
+    store: Arc<SharedStore>,
+    sink: Arc<dyn UpdateSink>,
     unit_id: u8,
+    options: Arc<ServiceOptions>,
+    controls: Arc<ServerControls>,
+    hooks: Arc<ServiceHooks>,
+    gateway: Arc<UpstreamPool>,
+    metrics: Arc<ServerMetrics>,
+    activity: ActivityLog,
+    started_at: Instant,
+    /// Last value reported by the `read_counter`.
+    read_count: Arc<AtomicU16>,
 }
 
 impl ModbusService {
-    pub fn new(store: Arc<RwLock<ModbusStore>>, app: AppHandle, unit_id: u8) -> Self {
+    pub fn new(
+        store: Arc<SharedStore>,
+        sink: Arc<dyn UpdateSink>,
+        unit_id: u8,
+        options: ServiceOptions,
+        controls: Arc<ServerControls>,
+        hooks: Arc<ServiceHooks>,
+        metrics: Arc<ServerMetrics>,
+    ) -> Self {
+        let gateway = Arc::new(UpstreamPool::new(
+            options.upstreams.clone(),
+            options.upstream_cache_ttl_ms.map(Duration::from_millis),
+            metrics.clone(),
+        ));
+        let activity = ActivityLog::new(sink.clone(), options.log_level);
+        let first_count = options
+            .read_counter
+            .filter(|counter| counter.store)
+            .and_then(|counter| {
+                let stored = store.view().values(counter.area, counter.address as usize, 1);
+                stored.first().copied()
+            })
+            .unwrap_or(0);
         Self {
             store,
-            app,
+            sink,
             unit_id,
+            options: Arc::new(options),
+            controls,
+            hooks,
+            gateway,
+            metrics,
+            activity,
+            started_at: Instant::now(),
+            read_count: Arc::new(AtomicU16::new(first_count)),
+        }
+    }
+
+    pub fn options(&self) -> &ServiceOptions {
+        &self.options
+    }
+
+    pub fn activity(&self) -> &ActivityLog {
+        &self.activity
+    }
+
+    pub fn metrics(&self) -> &Arc<ServerMetrics> {
+        &self.metrics
+    }
+}
+
+impl ServiceOptions {
+    pub fn store_defaults(&self) -> StoreDefaults {
+        StoreDefaults {
+            coil: self.coil_default,
+            register: self.register_default,
         }
     }
 }
 
 pub struct ConnectionService {
     inner: ModbusService,
-    connections: Arc<AtomicUsize>,
+    connection: Arc<ConnectionEntry>,
+    connections: ConnectionRegistry,
     on_status_update: Arc<dyn Fn() + Send + Sync>,
+    drop_rng: Option<Mutex<SplitMix64>>,
+    permit: Option<OwnedSemaphorePermit>,
+    span: Span,
 }
 
 impl ConnectionService {
     pub fn new(
         inner: ModbusService,
-        connections: Arc<AtomicUsize>,
+        connection: Arc<ConnectionEntry>,
+        connections: ConnectionRegistry,
         on_status_update: Arc<dyn Fn() + Send + Sync>,
+        permit: Option<OwnedSemaphorePermit>,
     ) -> Self {
+        let drop_rng = (inner.options.drop_probability > 0.0).then(|| {
+            let rng = match inner.options.drop_seed {
+                Some(seed) => SplitMix64::new(seed.wrapping_add(connection.id)),
+                None => SplitMix64::from_time(),
+            };
+            Mutex::new(rng)
+        });
+        let span = debug_span!("connection", id = connection.id, peer = %connection.peer);
+        span.in_scope(|| debug!("connection opened"));
+        inner.activity.info("Client connected", Some(connection.peer));
         Self {
             inner,
+            connection,
             connections,
             on_status_update,
+            drop_rng,
+            permit,
+            span,
         }
     }
+
+    fn should_drop(&self) -> bool {
+        let Some(rng) = &self.drop_rng else {
+            return false;
+        };
+        let probability = self.inner.options.drop_probability;
+        rng.lock()
+            .map(|mut rng| rng.next_f32() < probability)
+            .unwrap_or(false)
+    }
 }
 
 impl Drop for ConnectionService {
+    /// Connections are also dropped while the server or the whole app is tearing down, when the
+    /// event system may already be gone; the status emit is skipped then, and a panic from it
+    /// is swallowed rather than turning into an abort during unwinding.
     fn drop(&mut self) {
-        self.connections.fetch_sub(1, Ordering::SeqCst);
-        (self.on_status_update)();
+        self.span.in_scope(|| debug!("connection closed"));
+        self.connections.remove(self.connection.id);
+        drop(self.permit.take());
+        if self.inner.controls.is_shutting_down() {
+            return;
+        }
+        self.inner
+            .activity
+            .info("Client disconnected", Some(self.connection.peer));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| (self.on_status_update)()));
     }
 }
 
@@ -92,173 +656,867 @@ pub(crate) struct UpdatePayload {
     pub values: Vec<u16>,
 }
 
+#[derive(Clone, Serialize)]
+pub(crate) struct ChangedPayload {
+    pub area: DataArea,
+    pub revision: u64,
+    pub changes: Vec<(u16, u16)>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct FloatPayload {
+    pub address: u16,
+    pub value: f32,
+    pub word_order: WordOrder,
+    pub partial: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct ScanPayload {
+    pub peer: String,
+    pub units: Vec<u8>,
+    pub distinct_units: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct DeniedPayload {
+    pub peer: String,
+    pub unit: u8,
+    pub function: u8,
+    pub address: u16,
+    pub reason: &'static str,
+    pub exception: String,
+}
+
+struct RequestContext<'a> {
+    service: &'a ModbusService,
+    connection: &'a ConnectionEntry,
+    unit: u8,
+    function: u8,
+    writes: RefCell<Vec<WriteEvent>>,
+    commands: RefCell<Vec<CommandAction>>,
+    holding_writes: RefCell<Vec<(u16, usize)>>,
+}
+
+impl RequestContext<'_> {
+    fn record_write(&self, area: DataArea, offset: u16, values: Vec<u16>) {
+        if area == DataArea::HoldingRegisters {
+            if !self.service.options.float_pairs.is_empty() {
+                self.holding_writes.borrow_mut().push((offset, values.len()));
+            }
+            let commands = &self.service.options.command_registers;
+            self.commands
+                .borrow_mut()
+                .extend(commands::matching_actions(commands, offset, &values));
+        }
+        if !self.service.hooks.on_write.is_empty() {
+            self.writes.borrow_mut().push(WriteEvent {
+                area,
+                offset,
+                values: values.clone(),
+                unit: self.unit,
+                peer: self.connection.peer,
+            });
+        }
+        emit_write(&*self.service.sink, &self.service.store, area, offset, values);
+    }
+
+    /// Advances the read counter if `addr..addr + qty` of `area` covers it, returning its index
+    /// within the read and the value to report. Only the service's count changes, never the
+    /// store, so counting needs no write lock.
+    fn count_read(&self, area: DataArea, addr: u16, qty: u16) -> Option<(usize, u16)> {
+        let counter = self.service.options.read_counter?;
+        let index = counter.address.checked_sub(addr)?;
+        if counter.area != area || index >= qty {
+            return None;
+        }
+        let value = self.service.read_count.fetch_add(1, Ordering::SeqCst);
+        Some((index as usize, value.wrapping_add(1)))
+    }
+
+    fn run_write_hooks(&self) {
+        for event in self.writes.take() {
+            for hook in &self.service.hooks.on_write {
+                hook(&event);
+            }
+        }
+    }
+
+    /// Emits `modbus://float_updated` for every float pair touched by this request, decoded
+    /// from the published view once the write is released. A write that covered only one word
+    /// still emits, with `partial` set, combining it with the word already in the store.
+    fn emit_float_updates(&self) {
+        let writes = self.holding_writes.take();
+        if writes.is_empty() {
+            return;
+        }
+        let options = &self.service.options;
+        let store = self.service.store.view();
+        for &float_pair in &options.float_pairs {
+            let address = float_pair.address();
+            let word_order = float_pair.word_order(options.float_word_order);
+            let pair = address as usize..address as usize + 2;
+            let covered = writes
+                .iter()
+                .map(|&(offset, len)| {
+                    let written = offset as usize..offset as usize + len;
+                    pair.clone().filter(|index| written.contains(index)).count()
+                })
+                .max()
+                .unwrap_or(0);
+            if covered == 0 {
+                continue;
+            }
+            let Some(words) = store.holding_registers.slice(pair.start, pair.end) else {
+                continue;
+            };
+            let payload = FloatPayload {
+                address,
+                value: word_order.decode_f32(words[0], words[1]),
+                word_order,
+                partial: covered < 2,
+            };
+            self.service.sink.send("modbus://float_updated", payload);
+        }
+    }
+
+    fn run_commands(&self) {
+        let store = &self.service.store;
+        for action in self.commands.take() {
+            match action {
+                CommandAction::Freeze => {
+                    store.freeze(store.freeze_policy());
+                }
+                CommandAction::FreezeRejectWrites => {
+                    store.freeze(FreezeWritePolicy::Reject);
+                }
+                CommandAction::Unfreeze => {
+                    if store.unfreeze(true).is_some_and(|applied| applied > 0) {
+                        if let Ok(store) = store.read() {
+                            emit_store(&*self.service.sink, &store);
+                        }
+                    }
+                }
+                CommandAction::ResetMetrics => self.service.metrics.reset(),
+                CommandAction::ResetStore => {
+                    if let Ok(mut store) = store.write() {
+                        store.reset();
+                        emit_store(&*self.service.sink, &store);
+                    }
+                }
+            }
+        }
+    }
+
+    fn deny(&self, address: u16, reason: &'static str, exception: ExceptionCode) -> ExceptionCode {
+        let payload = DeniedPayload {
+            peer: self.connection.peer.to_string(),
+            unit: self.unit,
+            function: self.function,
+            address,
+            reason,
+            exception: format!("{exception:?}"),
+        };
+        self.service.sink.send("modbus://denied", payload);
+        exception
+    }
+}
+
 impl Service for ConnectionService {
     type Request = SlaveRequest<'static>;
     type Response = Option<Response>;
     type Exception = ExceptionCode;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Exception>> + Send>>;
 
+    /// The `tokio-modbus` TCP server awaits each returned future and writes its response
+    /// before reading the next frame from the connection, so responses on one connection are
+    /// always sent in request order, however long an individual future takes.
     fn call(&self, req: Self::Request) -> Self::Future {
-        let store = self.inner.store.clone();
-        let app = self.inner.app.clone();
-        let unit_id = self.inner.unit_id;
-        Box::pin(async move { handle_request(store, app, unit_id, req) })
+        let served = self.connection.record_request();
+        if let Some(limit) = self.inner.options.max_requests_per_connection {
+            if served >= limit {
+                self.connection.close();
+            }
+            if served > limit {
+                return Box::pin(async { Ok(None) });
+            }
+        }
+        let tracked = self
+            .inner
+            .options
+            .track_units
+            .as_ref()
+            .is_none_or(|units| units.contains(&req.slave));
+        let distinct_units = self.connection.record_unit(req.slave, tracked);
+        if let Some(threshold) = self.inner.options.scan_unit_threshold {
+            if distinct_units == threshold + 1 {
+                let payload = ScanPayload {
+                    peer: self.connection.peer.to_string(),
+                    units: self.connection.units(),
+                    distinct_units,
+                };
+                self.inner.sink.send("modbus://scan_detected", payload);
+            }
+        }
+        if self.inner.controls.is_paused() || self.should_drop() {
+            return Box::pin(async { Ok(None) });
+        }
+        let received = Instant::now();
+        let code = function_code(&req.request);
+        let metrics = self.inner.metrics.clone();
+        // Ahead of `handle_request`: the local store's checks do not apply to another device.
+        if self.inner.gateway.routes_unit(req.slave) {
+            let gateway = self.inner.gateway.clone();
+            let span = debug_span!(parent: &self.span, "forward", unit = req.slave);
+            return Box::pin(
+                async move {
+                    let result = gateway.forward(req.slave, req.request).await.map(Some);
+                    metrics.latency.record(code, received.elapsed());
+                    result
+                }
+                .instrument(span),
+            );
+        }
+        let service = self.inner.clone();
+        let connection = self.connection.clone();
+        let span = self.span.clone();
+        Box::pin(async move {
+            let result = span.in_scope(|| handle_request(&service, &connection, req));
+            metrics.latency.record(code, received.elapsed());
+            result
+        })
     }
 }
 
 fn handle_request(
-    store: Arc<RwLock<ModbusStore>>,
-    app: AppHandle,
-    unit_id: u8,
+    service: &ModbusService,
+    connection: &ConnectionEntry,
     req: SlaveRequest<'static>,
 ) -> Result<Option<Response>, ExceptionCode> {
-    if unit_id != 0 && req.slave != unit_id {
+    if service.unit_id != 0 && req.slave != service.unit_id {
         return Ok(None);
     }
+    let context = RequestContext {
+        service,
+        connection,
+        unit: req.slave,
+        function: function_code(&req.request),
+        writes: RefCell::default(),
+        commands: RefCell::default(),
+        holding_writes: RefCell::default(),
+    };
+    let address = request_address(&req.request);
+    let quantity = request_quantity(&req.request);
+    let span = debug_span!(
+        "request",
+        unit = context.unit,
+        function = context.function,
+        address,
+        outcome = field::Empty,
+    );
+    let _entered = span.enter();
+    let result = dispatch_request(&context, req.request);
+    context.run_write_hooks();
+    context.emit_float_updates();
+    context.run_commands();
+    match &result {
+        Ok(Some(_)) => span.record("outcome", "ok"),
+        Ok(None) => span.record("outcome", "no_response"),
+        Err(exception) => {
+            service.metrics.exceptions.record(ExceptionRecord {
+                timestamp_ms: unix_millis(),
+                peer: connection.peer.to_string(),
+                unit: context.unit,
+                function: context.function,
+                address,
+                quantity,
+                code: format!("{exception:?}"),
+            });
+            service.activity.warn(
+                format!(
+                    "Function 0x{:02X} for unit {} failed with {exception:?}",
+                    context.function, context.unit
+                ),
+                Some(connection.peer),
+            );
+            span.record("outcome", field::debug(exception))
+        }
+    };
+    debug!("request handled");
+    result
+}
+
+fn dispatch_request(
+    context: &RequestContext<'_>,
+    request: Request<'static>,
+) -> Result<Option<Response>, ExceptionCode> {
+    let service = context.service;
+    let store = &service.store;
+    let lenient = service.options.lenient_reads;
+    let overrides = store.overrides();
+    if let Some(busy_ms) = service.options.startup_busy_ms {
+        if service.started_at.elapsed() < Duration::from_millis(busy_ms) {
+            let addr = request_address(&request).unwrap_or_default();
+            return Err(context.deny(addr, "startup_busy", ExceptionCode::ServerDeviceBusy));
+        }
+    }
+    if let Some(area) = request_area(&request) {
+        if !service.options.areas.is_enabled(area) {
+            let addr = request_address(&request).unwrap_or_default();
+            let exception = service.options.exceptions.absent_area();
+            return Err(context.deny(addr, "area_disabled", exception));
+        }
+    }
+    if let Some(addr) = write_address(&request) {
+        match store.frozen_policy() {
+            Some(FreezeWritePolicy::Reject) => {
+                let exception = service.options.exceptions.write_rejected();
+                return Err(context.deny(addr, "frozen", exception));
+            }
+            Some(FreezeWritePolicy::Buffer) => return buffer_write(context, request),
+            Some(FreezeWritePolicy::ApplyLive) | None => {}
+        }
+    }
 
-    match req.request {
+    match request {
         Request::ReadCoils(addr, qty) => {
-            let store = store.read().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let values = slice_bool(&store.coils, addr, qty)?;
+            check_read_limit(context, addr, qty, service.options.max_read_coils)?;
+            let store = store.load().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
+            let mut values = slice(&store.coils, addr, qty, lenient)?;
+            ensure_initialized(service, &store, DataArea::Coils, addr, values.len())?;
+            overrides.apply_bits(DataArea::Coils, addr, &mut values);
+            pad_bits(service, &mut values);
             Ok(Some(Response::ReadCoils(values)))
         }
         Request::ReadDiscreteInputs(addr, qty) => {
-            let store = store.read().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let values = slice_bool(&store.discrete_inputs, addr, qty)?;
+            check_read_limit(context, addr, qty, service.options.max_read_coils)?;
+            let store = store.load().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
+            let mut values = slice(&store.discrete_inputs, addr, qty, lenient)?;
+            ensure_initialized(
+                service,
+                &store,
+                DataArea::DiscreteInputs,
+                addr,
+                values.len(),
+            )?;
+            overrides.apply_bits(DataArea::DiscreteInputs, addr, &mut values);
+            pad_bits(service, &mut values);
             Ok(Some(Response::ReadDiscreteInputs(values)))
         }
         Request::ReadInputRegisters(addr, qty) => {
-            let store = store.read().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let values = slice_u16(&store.input_registers, addr, qty)?;
+            check_read_limit(context, addr, qty, service.options.max_read_registers)?;
+            let store = store.load().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
+            let mut values = slice(&store.input_registers, addr, qty, lenient)?;
+            ensure_initialized(
+                service,
+                &store,
+                DataArea::InputRegisters,
+                addr,
+                values.len(),
+            )?;
+            let counted = context.count_read(DataArea::InputRegisters, addr, qty);
+            apply_count(counted, &mut values);
+            overrides.apply_words(DataArea::InputRegisters, addr, &mut values);
             Ok(Some(Response::ReadInputRegisters(values)))
         }
         Request::ReadHoldingRegisters(addr, qty) => {
-            let store = store.read().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let values = slice_u16(&store.holding_registers, addr, qty)?;
+            check_read_limit(context, addr, qty, service.options.max_read_registers)?;
+            let store = store.load().map_err(|_| ExceptionCode::ServerDeviceFailure)?;
+            let mut values = slice(&store.holding_registers, addr, qty, lenient)?;
+            ensure_initialized(
+                service,
+                &store,
+                DataArea::HoldingRegisters,
+                addr,
+                values.len(),
+            )?;
+            let counted = context.count_read(DataArea::HoldingRegisters, addr, qty);
+            apply_count(counted, &mut values);
+            overrides.apply_words(DataArea::HoldingRegisters, addr, &mut values);
             Ok(Some(Response::ReadHoldingRegisters(values)))
         }
         Request::WriteSingleCoil(addr, coil) => {
             let mut store = store
                 .write()
                 .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            write_bool(&mut store.coils, addr, coil)?;
-            emit_update(&app, DataArea::Coils, addr, vec![if coil { 1 } else { 0 }]);
+            range(store.coils.len(), addr, 1)?;
+            write_values(&mut store.coils, addr, &[coil])?;
+            store.mark_initialized(DataArea::Coils, addr as usize, 1);
+            context.record_write(DataArea::Coils, addr, vec![if coil { 1 } else { 0 }]);
             Ok(Some(Response::WriteSingleCoil(addr, coil)))
         }
         Request::WriteMultipleCoils(addr, coils) => {
+            if let Some(coil_packing) = &context.connection.coil_packing {
+                if coil_packing.take(addr, coils.len() as u16) == Some(false) {
+                    let exception = service.options.exceptions.malformed_packing();
+                    return Err(context.deny(addr, "strict_coil_packing", exception));
+                }
+            }
             let mut store = store
                 .write()
                 .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let written = write_bools(&mut store.coils, addr, &coils)?;
-            emit_update(&app, DataArea::Coils, addr, bools_to_u16(&coils));
+            range(store.coils.len(), addr, coils.len() as u16)?;
+            let written = write_values(&mut store.coils, addr, &coils)?;
+            store.mark_initialized(DataArea::Coils, addr as usize, coils.len());
+            context.record_write(DataArea::Coils, addr, bools_to_u16(&coils));
             Ok(Some(Response::WriteMultipleCoils(addr, written)))
         }
         Request::WriteSingleRegister(addr, word) => {
             let mut store = store
                 .write()
                 .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            write_u16(&mut store.holding_registers, addr, word)?;
-            emit_update(&app, DataArea::HoldingRegisters, addr, vec![word]);
+            range(store.holding_registers.len(), addr, 1)?;
+            write_values(&mut store.holding_registers, addr, &[word])?;
+            store.mark_initialized(DataArea::HoldingRegisters, addr as usize, 1);
+            context.record_write(DataArea::HoldingRegisters, addr, vec![word]);
             Ok(Some(Response::WriteSingleRegister(addr, word)))
         }
         Request::WriteMultipleRegisters(addr, words) => {
             let mut store = store
                 .write()
                 .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let written = write_u16s(&mut store.holding_registers, addr, &words)?;
-            emit_update(&app, DataArea::HoldingRegisters, addr, words.to_vec());
+            range(store.holding_registers.len(), addr, words.len() as u16)?;
+            let written = write_values(&mut store.holding_registers, addr, &words)?;
+            store.mark_initialized(DataArea::HoldingRegisters, addr as usize, words.len());
+            context.record_write(DataArea::HoldingRegisters, addr, words.to_vec());
             Ok(Some(Response::WriteMultipleRegisters(addr, written)))
         }
         Request::MaskWriteRegister(addr, and_mask, or_mask) => {
             let mut store = store
                 .write()
                 .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            let current = read_single_u16(&store.holding_registers, addr)?;
-            let next = (current & and_mask) | (or_mask);
-            write_u16(&mut store.holding_registers, addr, next)?;
-            emit_update(&app, DataArea::HoldingRegisters, addr, vec![next]);
+            let current = read_single(&store.holding_registers, addr)?;
+            ensure_initialized(service, &store, DataArea::HoldingRegisters, addr, 1)?;
+            let next = apply_mask(current, and_mask, or_mask);
+            write_values(&mut store.holding_registers, addr, &[next])?;
+            store.mark_initialized(DataArea::HoldingRegisters, addr as usize, 1);
+            context.record_write(DataArea::HoldingRegisters, addr, vec![next]);
             Ok(Some(Response::MaskWriteRegister(addr, and_mask, or_mask)))
         }
         Request::ReadWriteMultipleRegisters(read_addr, read_qty, write_addr, words) => {
+            check_read_limit(
+                context,
+                read_addr,
+                read_qty,
+                service.options.max_read_registers,
+            )?;
+            // The write guard spans both halves, and snapshot readers only see the store once
+            // the guard is released, so concurrent readers observe all-old or all-new values.
             let mut store = store
                 .write()
                 .map_err(|_| ExceptionCode::ServerDeviceFailure)?;
-            write_u16s(&mut store.holding_registers, write_addr, &words)?;
-            emit_update(&app, DataArea::HoldingRegisters, write_addr, words.to_vec());
-            let values = slice_u16(&store.holding_registers, read_addr, read_qty)?;
+            let len = store.holding_registers.len();
+            let (read_start, read_end) = read_range(len, read_addr, read_qty, lenient)?;
+            let (write_start, write_end) = range(len, write_addr, words.len() as u16)?;
+            // Checked before writing so a refused request changes nothing. Addresses this request
+            // writes count as initialized; only the read addresses around them are checked.
+            let before = read_end.min(write_start);
+            if read_start < before {
+                ensure_initialized(
+                    service,
+                    &store,
+                    DataArea::HoldingRegisters,
+                    read_addr,
+                    before - read_start,
+                )?;
+            }
+            let after = read_start.max(write_end);
+            if after < read_end {
+                ensure_initialized(
+                    service,
+                    &store,
+                    DataArea::HoldingRegisters,
+                    after as u16,
+                    read_end - after,
+                )?;
+            }
+            let read_first = service
+                .options
+                .response_quirks
+                .contains(ResponseQuirks::READ_BEFORE_WRITE)
+                .then(|| slice(&store.holding_registers, read_addr, read_qty, lenient))
+                .transpose()?;
+            write_values(&mut store.holding_registers, write_addr, &words)?;
+            store.mark_initialized(DataArea::HoldingRegisters, write_addr as usize, words.len());
+            context.record_write(DataArea::HoldingRegisters, write_addr, words.to_vec());
+            let mut values = match (service.store.frozen(), read_first) {
+                (Some(frozen), _) => {
+                    slice(&frozen.holding_registers, read_addr, read_qty, lenient)?
+                }
+                (None, Some(values)) => values,
+                (None, None) => slice(&store.holding_registers, read_addr, read_qty, lenient)?,
+            };
+            overrides.apply_words(DataArea::HoldingRegisters, read_addr, &mut values);
             Ok(Some(Response::ReadWriteMultipleRegisters(values)))
         }
-        Request::ReportServerId
-        | Request::ReadDeviceIdentification(_, _)
+        Request::Custom(ENCAPSULATED_INTERFACE_TRANSPORT, data) => {
+            let (&mei_type, payload) = data.split_first().ok_or(ExceptionCode::IllegalDataValue)?;
+            let reply = service.hooks.mei.dispatch(mei_type, payload)?;
+            let mut body = Vec::with_capacity(reply.len() + 1);
+            body.push(mei_type);
+            body.extend_from_slice(&reply);
+            Ok(Some(Response::Custom(
+                ENCAPSULATED_INTERFACE_TRANSPORT,
+                Bytes::from(body),
+            )))
+        }
+        Request::ReportServerId => {
+            let controls = &service.controls;
+            let running = controls
+                .run_indicator()
+                .or(service.options.run_indicator)
+                .unwrap_or(!controls.is_paused());
+            Ok(Some(Response::ReportServerId(service.unit_id, running, Vec::new())))
+        }
+        Request::ReadDeviceIdentification(_, _)
         | Request::Custom(_, _) => Err(ExceptionCode::IllegalFunction),
     }
 }
 
-pub(crate) fn emit_update(app: &AppHandle, area: DataArea, offset: u16, values: Vec<u16>) {
-    let payload = UpdatePayload {
-        area,
-        offset,
-        values,
+/// Answers a master write while the store is frozen with `FreezeWritePolicy::Buffer`. The
+/// write is checked against the live store and queued, and the reply is the one it will have
+/// once applied; FC23 reads from the frozen view. Buffered writes never run hooks or command
+/// registers, and the store is re-sent to the frontend once unfreeze has applied them.
+fn buffer_write(
+    context: &RequestContext<'_>,
+    request: Request<'static>,
+) -> Result<Option<Response>, ExceptionCode> {
+    let service = context.service;
+    let store = &service.store;
+    let (write, response) = match request {
+        Request::WriteSingleCoil(addr, coil) => (
+            BufferedWrite::Coils {
+                offset: addr,
+                values: vec![coil],
+            },
+            Response::WriteSingleCoil(addr, coil),
+        ),
+        Request::WriteMultipleCoils(addr, coils) => {
+            if let Some(coil_packing) = &context.connection.coil_packing {
+                if coil_packing.take(addr, coils.len() as u16) == Some(false) {
+                    let exception = service.options.exceptions.malformed_packing();
+                    return Err(context.deny(addr, "strict_coil_packing", exception));
+                }
+            }
+            let written = coils.len() as u16;
+            (
+                BufferedWrite::Coils {
+                    offset: addr,
+                    values: coils.to_vec(),
+                },
+                Response::WriteMultipleCoils(addr, written),
+            )
+        }
+        Request::WriteSingleRegister(addr, word) => (
+            BufferedWrite::Registers {
+                offset: addr,
+                values: vec![word],
+            },
+            Response::WriteSingleRegister(addr, word),
+        ),
+        Request::WriteMultipleRegisters(addr, words) => {
+            let written = words.len() as u16;
+            (
+                BufferedWrite::Registers {
+                    offset: addr,
+                    values: words.to_vec(),
+                },
+                Response::WriteMultipleRegisters(addr, written),
+            )
+        }
+        Request::MaskWriteRegister(addr, and_mask, or_mask) => (
+            BufferedWrite::Mask {
+                offset: addr,
+                and_mask,
+                or_mask,
+            },
+            Response::MaskWriteRegister(addr, and_mask, or_mask),
+        ),
+        Request::ReadWriteMultipleRegisters(read_addr, read_qty, write_addr, words) => {
+            check_read_limit(
+                context,
+                read_addr,
+                read_qty,
+                service.options.max_read_registers,
+            )?;
+            let frozen = store.frozen().ok_or(ExceptionCode::ServerDeviceBusy)?;
+            let lenient = service.options.lenient_reads;
+            let mut values = slice(&frozen.holding_registers, read_addr, read_qty, lenient)?;
+            ensure_initialized(
+                service,
+                &frozen,
+                DataArea::HoldingRegisters,
+                read_addr,
+                values.len(),
+            )?;
+            store
+                .overrides()
+                .apply_words(DataArea::HoldingRegisters, read_addr, &mut values);
+            (
+                BufferedWrite::Registers {
+                    offset: write_addr,
+                    values: words.to_vec(),
+                },
+                Response::ReadWriteMultipleRegisters(values),
+            )
+        }
+        _ => return Err(ExceptionCode::IllegalFunction),
     };
-    let _ = app.emit("modbus://updated", payload);
+    if !write.fits(&store.view()) {
+        return Err(ExceptionCode::IllegalDataAddress);
+    }
+    // Unfrozen since the policy was checked: the master retries and writes the live store.
+    if !store.buffer_write(write) {
+        return Err(ExceptionCode::ServerDeviceBusy);
+    }
+    Ok(Some(response))
 }
 
-fn slice_bool(values: &[bool], addr: u16, qty: u16) -> Result<Vec<bool>, ExceptionCode> {
-    let (start, end) = range(values.len(), addr, qty)?;
-    Ok(values[start..end].to_vec())
+/// Validates the quantity of a read before its address, as the spec orders the checks: zero is
+/// never a valid quantity, and `limit` optionally caps it below the protocol maximum.
+fn check_read_limit(
+    context: &RequestContext<'_>,
+    addr: u16,
+    qty: u16,
+    limit: Option<u16>,
+) -> Result<(), ExceptionCode> {
+    if qty == 0 {
+        return Err(ExceptionCode::IllegalDataValue);
+    }
+    if limit.is_some_and(|limit| qty > limit) {
+        return Err(context.deny(addr, "max_read", ExceptionCode::IllegalDataValue));
+    }
+    Ok(())
 }
 
-fn slice_u16(values: &[u16], addr: u16, qty: u16) -> Result<Vec<u16>, ExceptionCode> {
-    let (start, end) = range(values.len(), addr, qty)?;
-    Ok(values[start..end].to_vec())
+/// Extending the bits to a whole number of bytes leaves the encoded byte count unchanged, so
+/// this only turns the trailing padding bits on.
+fn pad_bits(service: &ModbusService, values: &mut Vec<bool>) {
+    let quirks = service.options.response_quirks;
+    if quirks.contains(ResponseQuirks::PAD_BITS_WITH_ONES) {
+        values.resize(values.len().div_ceil(8) * 8, true);
+    }
 }
 
-fn read_single_u16(values: &[u16], addr: u16) -> Result<u16, ExceptionCode> {
-    let index = addr as usize;
-    values
-        .get(index)
-        .copied()
-        .ok_or(ExceptionCode::IllegalDataAddress)
+fn ensure_initialized(
+    service: &ModbusService,
+    store: &ModbusStore,
+    area: DataArea,
+    addr: u16,
+    len: usize,
+) -> Result<(), ExceptionCode> {
+    if service.options.sparse && !store.is_initialized(area, addr as usize, len) {
+        return Err(service.options.exceptions.address_gap());
+    }
+    Ok(())
 }
 
-fn write_bool(values: &mut [bool], addr: u16, value: bool) -> Result<(), ExceptionCode> {
-    let index = addr as usize;
-    if index >= values.len() {
-        return Err(ExceptionCode::IllegalDataAddress);
+fn write_address(request: &Request<'_>) -> Option<u16> {
+    match request {
+        Request::WriteSingleCoil(addr, _)
+        | Request::WriteMultipleCoils(addr, _)
+        | Request::WriteSingleRegister(addr, _)
+        | Request::WriteMultipleRegisters(addr, _)
+        | Request::MaskWriteRegister(addr, _, _)
+        | Request::ReadWriteMultipleRegisters(_, _, addr, _) => Some(*addr),
+        _ => None,
     }
-    values[index] = value;
-    Ok(())
 }
 
-fn write_bools(values: &mut [bool], addr: u16, data: &[bool]) -> Result<u16, ExceptionCode> {
-    let start = addr as usize;
-    let end = start + data.len();
-    if end > values.len() {
-        return Err(ExceptionCode::IllegalDataAddress);
+fn request_quantity(request: &Request<'_>) -> Option<u16> {
+    match request {
+        Request::ReadCoils(_, qty)
+        | Request::ReadDiscreteInputs(_, qty)
+        | Request::ReadInputRegisters(_, qty)
+        | Request::ReadHoldingRegisters(_, qty)
+        | Request::ReadWriteMultipleRegisters(_, qty, _, _) => Some(*qty),
+        Request::WriteSingleCoil(_, _)
+        | Request::WriteSingleRegister(_, _)
+        | Request::MaskWriteRegister(_, _, _) => Some(1),
+        Request::WriteMultipleCoils(_, coils) => Some(coils.len() as u16),
+        Request::WriteMultipleRegisters(_, words) => Some(words.len() as u16),
+        _ => None,
     }
-    values[start..end].copy_from_slice(data);
-    Ok(data.len() as u16)
 }
 
-fn write_u16(values: &mut [u16], addr: u16, value: u16) -> Result<(), ExceptionCode> {
-    let index = addr as usize;
-    if index >= values.len() {
-        return Err(ExceptionCode::IllegalDataAddress);
+fn request_area(request: &Request<'_>) -> Option<DataArea> {
+    match request {
+        Request::ReadCoils(_, _)
+        | Request::WriteSingleCoil(_, _)
+        | Request::WriteMultipleCoils(_, _) => Some(DataArea::Coils),
+        Request::ReadDiscreteInputs(_, _) => Some(DataArea::DiscreteInputs),
+        Request::ReadInputRegisters(_, _) => Some(DataArea::InputRegisters),
+        Request::ReadHoldingRegisters(_, _)
+        | Request::WriteSingleRegister(_, _)
+        | Request::WriteMultipleRegisters(_, _)
+        | Request::MaskWriteRegister(_, _, _)
+        | Request::ReadWriteMultipleRegisters(_, _, _, _) => Some(DataArea::HoldingRegisters),
+        _ => None,
     }
-    values[index] = value;
-    Ok(())
 }
 
-fn write_u16s(values: &mut [u16], addr: u16, data: &[u16]) -> Result<u16, ExceptionCode> {
-    let start = addr as usize;
-    let end = start + data.len();
-    if end > values.len() {
+fn request_address(request: &Request<'_>) -> Option<u16> {
+    match request {
+        Request::ReadCoils(addr, _)
+        | Request::ReadDiscreteInputs(addr, _)
+        | Request::ReadInputRegisters(addr, _)
+        | Request::ReadHoldingRegisters(addr, _)
+        | Request::ReadWriteMultipleRegisters(addr, _, _, _) => Some(*addr),
+        _ => write_address(request),
+    }
+}
+
+fn function_code(request: &Request<'_>) -> u8 {
+    match request {
+        Request::ReadCoils(_, _) => 0x01,
+        Request::ReadDiscreteInputs(_, _) => 0x02,
+        Request::ReadHoldingRegisters(_, _) => 0x03,
+        Request::ReadInputRegisters(_, _) => 0x04,
+        Request::WriteSingleCoil(_, _) => 0x05,
+        Request::WriteSingleRegister(_, _) => 0x06,
+        Request::WriteMultipleCoils(_, _) => 0x0F,
+        Request::WriteMultipleRegisters(_, _) => 0x10,
+        Request::ReportServerId => 0x11,
+        Request::MaskWriteRegister(_, _, _) => 0x16,
+        Request::ReadWriteMultipleRegisters(_, _, _, _) => 0x17,
+        Request::ReadDeviceIdentification(_, _) => 0x2B,
+        Request::Custom(code, _) => *code,
+    }
+}
+
+pub(crate) fn emit_store(sink: &dyn UpdateSink, store: &ModbusStore) {
+    emit_update(sink, DataArea::Coils, 0, bools_to_u16(&store.coils.to_vec()));
+    emit_update(
+        sink,
+        DataArea::DiscreteInputs,
+        0,
+        bools_to_u16(&store.discrete_inputs.to_vec()),
+    );
+    emit_update(
+        sink,
+        DataArea::InputRegisters,
+        0,
+        store.input_registers.to_vec(),
+    );
+    emit_update(
+        sink,
+        DataArea::HoldingRegisters,
+        0,
+        store.holding_registers.to_vec(),
+    );
+}
+
+/// Puts the read counter's value from `count_read` at its index within a read response.
+fn apply_count(counted: Option<(usize, u16)>, values: &mut [u16]) {
+    if let Some((index, value)) = counted {
+        if let Some(slot) = values.get_mut(index) {
+            *slot = value;
+        }
+    }
+}
+
+/// Emits a write made under `shared`'s write guard. With `diff_updates` on, the values are
+/// compared against the published view, which still holds the pre-write state, and only the
+/// changed `(address, value)` pairs go out as `modbus://changed` together with the revision the
+/// store reaches once the guard is released. Mostly-changed blocks fall back to a full update.
+pub(crate) fn emit_write(
+    sink: &dyn UpdateSink,
+    shared: &SharedStore,
+    area: DataArea,
+    offset: u16,
+    values: Vec<u16>,
+) {
+    if let Some(debouncer) = shared.debouncer() {
+        debouncer.emit(area, offset, values);
+        return;
+    }
+    if !shared.diff_updates() {
+        emit_update(sink, area, offset, values);
+        return;
+    }
+    let before = shared.view().values(area, offset as usize, values.len());
+    if before.len() != values.len() {
+        emit_update(sink, area, offset, values);
+        return;
+    }
+    let changes: Vec<(u16, u16)> = before
+        .iter()
+        .zip(&values)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(index, (_, new))| (offset + index as u16, *new))
+        .collect();
+    if changes.len() * 2 > values.len() {
+        emit_update(sink, area, offset, values);
+        return;
+    }
+    if changes.is_empty() {
+        return;
+    }
+    let payload = ChangedPayload {
+        area,
+        revision: shared.revision() + 1,
+        changes,
+    };
+    sink.send("modbus://changed", payload);
+}
+
+pub(crate) fn emit_update(sink: &dyn UpdateSink, area: DataArea, offset: u16, values: Vec<u16>) {
+    sink.emit_update(UpdatePayload {
+        area,
+        offset,
+        values,
+    });
+}
+
+fn slice<T: Cell>(
+    values: &Area<T>,
+    addr: u16,
+    qty: u16,
+    lenient: bool,
+) -> Result<Vec<T>, ExceptionCode> {
+    let (start, end) = read_range(values.len(), addr, qty, lenient)?;
+    values
+        .slice(start, end)
+        .ok_or(ExceptionCode::IllegalDataAddress)
+}
+
+/// FC22: bits set in `and_mask` keep their current value and bits cleared in it are taken from
+/// `or_mask`, i.e. `(current & and_mask) | (or_mask & !and_mask)`.
+pub(crate) fn apply_mask(current: u16, and_mask: u16, or_mask: u16) -> u16 {
+    (current & and_mask) | (or_mask & !and_mask)
+}
+
+fn read_single<T: Cell>(values: &Area<T>, addr: u16) -> Result<T, ExceptionCode> {
+    values
+        .get(addr as usize)
+        .ok_or(ExceptionCode::IllegalDataAddress)
+}
+
+fn write_values<T: Cell>(
+    values: &mut Area<T>,
+    addr: u16,
+    data: &[T],
+) -> Result<u16, ExceptionCode> {
+    if !values.write(addr as usize, data) {
         return Err(ExceptionCode::IllegalDataAddress);
     }
-    values[start..end].copy_from_slice(data);
     Ok(data.len() as u16)
 }
 
+fn read_range(
+    len: usize,
+    addr: u16,
+    qty: u16,
+    lenient: bool,
+) -> Result<(usize, usize), ExceptionCode> {
+    let start = addr as usize;
+    if lenient && start < len {
+        return Ok((start, len.min(start + qty as usize)));
+    }
+    range(len, addr, qty)
+}
+
 fn range(len: usize, addr: u16, qty: u16) -> Result<(usize, usize), ExceptionCode> {
     let start = addr as usize;
     let end = start + qty as usize;
@@ -268,6 +1526,347 @@ fn range(len: usize, addr: u16, qty: u16) -> Result<(usize, usize), ExceptionCod
     Ok((start, end))
 }
 
+/// Packs bits LSB-first into bytes, matching the Modbus wire layout for coils.
+pub(crate) fn pack_bits(values: &[bool]) -> Vec<u8> {
+    let mut packed = vec![0u8; values.len().div_ceil(8)];
+    for (index, value) in values.iter().enumerate() {
+        if *value {
+            packed[index / 8] |= 1 << (index % 8);
+        }
+    }
+    packed
+}
+
 pub(crate) fn bools_to_u16(values: &[bool]) -> Vec<u16> {
     values.iter().map(|value| if *value { 1 } else { 0 }).collect()
 }
+
+/// Groups `(address, value)` pairs sorted by address into runs of consecutive addresses.
+pub(crate) fn contiguous_runs(values: Vec<(u16, u16)>) -> Vec<(u16, Vec<u16>)> {
+    let mut runs: Vec<(u16, Vec<u16>)> = Vec::new();
+    for (address, value) in values {
+        match runs.last_mut() {
+            Some((start, run)) if *start as usize + run.len() == address as usize => {
+                run.push(value)
+            }
+            _ => runs.push((address, vec![value])),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_modbus::client::Reader;
+
+    use super::*;
+    use crate::exceptions::ExceptionKind;
+    use crate::harness::{RecordingSink, TestServer};
+
+    #[test]
+    fn emit_write_sends_only_changed_values_with_diff_updates() {
+        let shared = SharedStore::new(ModbusStore::new(8));
+        shared.set_diff_updates(true);
+        let sink = RecordingSink::default();
+        let mut store = shared.write().unwrap();
+        store.write_values(DataArea::HoldingRegisters, 0, &[0, 0, 5, 0]);
+        emit_write(
+            &sink,
+            &shared,
+            DataArea::HoldingRegisters,
+            0,
+            vec![0, 0, 5, 0],
+        );
+        drop(store);
+
+        let changed = sink.events("modbus://changed");
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0]["revision"], 1);
+        assert_eq!(changed[0]["changes"], serde_json::json!([[2, 5]]));
+        assert!(sink.updates().is_empty());
+        assert_eq!(shared.revision(), 1);
+    }
+
+    #[test]
+    fn emit_write_falls_back_to_a_full_update_for_mostly_changed_blocks() {
+        let shared = SharedStore::new(ModbusStore::new(8));
+        shared.set_diff_updates(true);
+        let sink = RecordingSink::default();
+        let mut store = shared.write().unwrap();
+        store.write_values(DataArea::Coils, 4, &[1, 1, 0]);
+        emit_write(&sink, &shared, DataArea::Coils, 4, vec![1, 1, 0]);
+        drop(store);
+
+        let updates = sink.updates();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            (updates[0].offset, updates[0].values.clone()),
+            (4, vec![1, 1, 0])
+        );
+        assert!(sink.events("modbus://changed").is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_counter_advances_without_writing_the_store() {
+        let mut store = ModbusStore::new(8);
+        store.write_values(DataArea::HoldingRegisters, 3, &[41]);
+        let options = ServiceOptions {
+            read_counter: Some(ReadCounter {
+                area: DataArea::HoldingRegisters,
+                address: 3,
+                store: true,
+            }),
+            ..ServiceOptions::default()
+        };
+        let server = TestServer::start(store, 1, options).await;
+        let mut client = server.client(1).await;
+        assert_eq!(
+            client.read_holding_registers(2, 2).await.unwrap(),
+            Ok(vec![0, 42])
+        );
+        assert_eq!(
+            client.read_holding_registers(3, 1).await.unwrap(),
+            Ok(vec![43])
+        );
+        assert_eq!(
+            client.read_holding_registers(0, 3).await.unwrap(),
+            Ok(vec![0, 0, 0])
+        );
+        assert_eq!(
+            client.read_input_registers(3, 1).await.unwrap(),
+            Ok(vec![0])
+        );
+        assert_eq!(server.store.revision(), 0);
+        let stored = server.store.view().values(DataArea::HoldingRegisters, 3, 1);
+        assert_eq!(stored, vec![41]);
+        server.stop().await;
+    }
+
+    #[test]
+    fn apply_mask_follows_the_spec() {
+        // The example from the Modbus application protocol spec, 6.16.
+        assert_eq!(apply_mask(0x12, 0xF2, 0x25), 0x17);
+        // OR bits under a set AND bit do not change the current value.
+        assert_eq!(apply_mask(0x0000, 0x00FF, 0x00FF), 0x0000);
+        assert_eq!(apply_mask(0xFFFF, 0x0000, 0x0000), 0x0000);
+        assert_eq!(apply_mask(0xABCD, 0xFFFF, 0x1234), 0xABCD);
+    }
+
+    #[tokio::test]
+    async fn out_of_range_mask_write_leaves_the_store_alone() {
+        let server = TestServer::start(ModbusStore::new(8), 1, ServiceOptions::default()).await;
+        let mut client = server.client(1).await;
+        let result = client
+            .masked_write_register(8, 0x0000, 0xFFFF)
+            .await
+            .unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        assert_eq!(server.store.revision(), 0);
+        assert!(server.sink.updates().is_empty());
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn refused_read_write_multiple_writes_nothing() {
+        let mut store = ModbusStore::new(8);
+        store.write_values(DataArea::HoldingRegisters, 0, &[1, 2]);
+        store.mark_initialized(DataArea::HoldingRegisters, 0, 2);
+        let options = ServiceOptions {
+            sparse: true,
+            ..ServiceOptions::default()
+        };
+        let server = TestServer::start(store, 1, options).await;
+        let mut client = server.client(1).await;
+        let result = client
+            .read_write_multiple_registers(0, 4, 2, &[7])
+            .await
+            .unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        assert_eq!(server.store.revision(), 0);
+        assert!(server.sink.updates().is_empty());
+        assert!(!server
+            .store
+            .view()
+            .is_initialized(DataArea::HoldingRegisters, 2, 1));
+
+        let result = client
+            .read_write_multiple_registers(0, 4, 2, &[7, 8])
+            .await
+            .unwrap();
+        assert_eq!(result, Ok(vec![1, 2, 7, 8]));
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn resize_while_a_client_polls() {
+        let server = TestServer::start(ModbusStore::new(8), 1, ServiceOptions::default()).await;
+        let mut client = server.client(1).await;
+        let resizer = {
+            let store = server.store.clone();
+            tokio::spawn(async move {
+                for round in 0..50 {
+                    let size = if round % 2 == 0 { 2 } else { 16 };
+                    store
+                        .write()
+                        .unwrap()
+                        .resize(DataArea::HoldingRegisters, size)
+                        .unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+        let (mut served, mut refused) = (0, 0);
+        while !resizer.is_finished() {
+            match client.read_holding_registers(0, 4).await.unwrap() {
+                Ok(words) => {
+                    assert_eq!(words.len(), 4);
+                    served += 1;
+                }
+                Err(exception) => {
+                    assert_eq!(exception, ExceptionCode::IllegalDataAddress);
+                    refused += 1;
+                }
+            }
+        }
+        resizer.await.unwrap();
+        assert!(served + refused > 0);
+        let words = client.read_holding_registers(12, 4).await.unwrap().unwrap();
+        assert_eq!(words, vec![0; 4]);
+        server.stop().await;
+    }
+
+    #[test]
+    fn pack_bits_is_lsb_first() {
+        assert_eq!(pack_bits(&[]), Vec::<u8>::new());
+        assert_eq!(pack_bits(&[true, false, false, false]), vec![0x01]);
+        let mut bits = [false; 10];
+        bits[1] = true;
+        bits[7] = true;
+        bits[9] = true;
+        assert_eq!(pack_bits(&bits), vec![0x82, 0x02]);
+    }
+
+    fn connection_service(
+        controls: Arc<ServerControls>,
+        on_status_update: Arc<dyn Fn() + Send + Sync>,
+    ) -> (ConnectionService, ConnectionRegistry) {
+        let service = ModbusService::new(
+            Arc::new(SharedStore::new(ModbusStore::new(8))),
+            Arc::new(RecordingSink::default()),
+            1,
+            ServiceOptions::default(),
+            controls,
+            Arc::default(),
+            Arc::default(),
+        );
+        let connections = ConnectionRegistry::default();
+        let connection = connections.register("127.0.0.1:40000".parse().unwrap(), None);
+        let service = ConnectionService::new(
+            service,
+            connection,
+            connections.clone(),
+            on_status_update,
+            None,
+        );
+        (service, connections)
+    }
+
+    #[test]
+    fn connection_dropped_during_shutdown_skips_the_status_emit() {
+        let controls = Arc::new(ServerControls::default());
+        let emitted = Arc::new(AtomicBool::new(false));
+        let on_status_update = {
+            let emitted = emitted.clone();
+            Arc::new(move || emitted.store(true, Ordering::SeqCst))
+        };
+        let (service, connections) = connection_service(controls.clone(), on_status_update);
+        controls.shut_down();
+        drop(service);
+        assert_eq!(connections.count(), 0);
+        assert!(!emitted.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panicking_status_emit_does_not_escape_drop() {
+        let on_status_update = Arc::new(|| panic!("event loop gone"));
+        let (service, connections) = connection_service(Arc::default(), on_status_update);
+        drop(service);
+        assert_eq!(connections.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn read_write_multiple_is_seen_all_old_or_all_new() {
+        for snapshot_reads in [false, true] {
+            let options = ServiceOptions {
+                snapshot_reads,
+                ..ServiceOptions::default()
+            };
+            let server = TestServer::start(ModbusStore::new(16), 1, options).await;
+            let mut writer = server.client(1).await;
+            let mut reader = server.client(1).await;
+            let writes = tokio::spawn(async move {
+                for round in 1..=200u16 {
+                    writer
+                        .read_write_multiple_registers(8, 1, 0, &[round; 8])
+                        .await
+                        .unwrap()
+                        .unwrap();
+                }
+            });
+            while !writes.is_finished() {
+                let words = reader.read_holding_registers(0, 8).await.unwrap().unwrap();
+                assert!(words.iter().all(|word| *word == words[0]), "{words:?}");
+            }
+            writes.await.unwrap();
+            let words = reader.read_holding_registers(0, 8).await.unwrap().unwrap();
+            assert_eq!(words, vec![200; 8]);
+            server.stop().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn zero_quantity_reads_are_illegal_data_value() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let options = ServiceOptions {
+            max_read_registers: Some(10),
+            ..ServiceOptions::default()
+        };
+        let server = TestServer::start(ModbusStore::new(8), 1, options).await;
+        // Sent raw, as the client library may refuse to encode a zero quantity itself.
+        let mut stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+        for function in [0x01, 0x02, 0x03, 0x04] {
+            let frame = [0, 1, 0, 0, 0, 6, 1, function, 0, 0, 0, 0];
+            stream.write_all(&frame).await.unwrap();
+            let mut response = [0u8; 9];
+            stream.read_exact(&mut response).await.unwrap();
+            assert_eq!(response[7..], [function | 0x80, 0x03]);
+        }
+        // Refused before the configured limit is consulted, so no max_read deny is reported.
+        assert!(server.sink.events("modbus://denied").is_empty());
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn absent_areas_are_refused() {
+        let mut options = ServiceOptions::default();
+        options.areas.holding_regs_enabled = false;
+        options.exceptions.absent_area = ExceptionKind::IllegalDataAddress;
+        let server = TestServer::start(ModbusStore::new(8), 1, options).await;
+        let mut client = server.client(1).await;
+        let result = client.read_holding_registers(0, 1).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        let result = client.write_single_register(2, 7).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        assert_eq!(server.store.revision(), 0);
+        client.read_input_registers(0, 1).await.unwrap().unwrap();
+
+        let denied = server.sink.events("modbus://denied");
+        assert_eq!(denied.len(), 2);
+        assert!(denied
+            .iter()
+            .all(|event| event["reason"] == "area_disabled"));
+        assert_eq!(denied[1]["address"], 2);
+        server.stop().await;
+    }
+}
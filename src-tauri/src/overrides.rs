@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::modbus::DataArea;
+
+/// An input or holding register that counts FC03/FC04 reads covering it, for masters that
+/// poll a liveness counter to make sure replies are not cached. Each such read reports the
+/// count incremented by one, wrapping at `u16::MAX`. The count is kept by the server and laid
+/// over the response, so reads never take the write lock and the register itself is left
+/// alone. It starts at 0 on every start, or with `store` at the register's value then, e.g.
+/// one persisted through `mmap_path`. A read override on the same address wins: masters see
+/// the override while the count keeps advancing underneath.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ReadCounter {
+    pub area: DataArea,
+    pub address: u16,
+    #[serde(default)]
+    pub store: bool,
+}
+
+/// Per-address values reported to masters instead of the stored value, e.g. to emulate a
+/// sensor stuck at a reading. Local commands keep seeing the real store contents.
+#[derive(Default)]
+pub struct ReadOverrides {
+    values: Mutex<HashMap<(DataArea, u16), u16>>,
+}
+
+impl ReadOverrides {
+    pub fn set(&self, area: DataArea, offset: u16, value: u16) {
+        if let Ok(mut values) = self.values.lock() {
+            values.insert((area, offset), value);
+        }
+    }
+
+    pub fn clear(&self, area: DataArea, offset: u16) -> bool {
+        self.values
+            .lock()
+            .is_ok_and(|mut values| values.remove(&(area, offset)).is_some())
+    }
+
+    pub fn apply_bits(&self, area: DataArea, addr: u16, bits: &mut [bool]) {
+        self.apply(area, addr, bits.len(), |index, value| bits[index] = value != 0);
+    }
+
+    pub fn apply_words(&self, area: DataArea, addr: u16, words: &mut [u16]) {
+        self.apply(area, addr, words.len(), |index, value| words[index] = value);
+    }
+
+    fn apply(&self, area: DataArea, addr: u16, len: usize, mut set: impl FnMut(usize, u16)) {
+        let Ok(values) = self.values.lock() else {
+            return;
+        };
+        if values.is_empty() {
+            return;
+        }
+        for (&(override_area, offset), &value) in values.iter() {
+            let Some(index) = offset.checked_sub(addr) else {
+                continue;
+            };
+            if override_area == area && (index as usize) < len {
+                set(index as usize, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tauri::{AppHandle, Manager};
+
+/// Resolves `file_name` inside the app data directory, failing with a readable message when
+/// the platform cannot provide one.
+pub fn app_data_file(app: &AppHandle, file_name: &str) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|err| format!("App data directory is unavailable: {err}"))?;
+    Ok(dir.join(file_name))
+}
+
+pub fn read_file(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read(path)
+        .map(Some)
+        .map_err(|err| format!("Cannot read {}: {err}", path.display()))
+}
+
+/// Creates `dir` and any missing parents.
+pub fn create_dir(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir)
+        .map_err(|err| format!("Cannot create directory {}: {err}", dir.display()))
+}
+
+/// Creates missing parent directories, then writes to a sibling temporary file and renames it
+/// over `path`, so a failed write never leaves a truncated file behind.
+pub fn write_file(path: &Path, data: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        create_dir(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, data).map_err(|err| format!("Cannot write {}: {err}", temp.display()))?;
+    fs::rename(&temp, path).map_err(|err| {
+        let _ = fs::remove_file(&temp);
+        format!("Cannot replace {}: {err}", path.display())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    #[test]
+    fn write_file_replaces_and_read_file_reads_back() {
+        let dir = TempDir::new();
+        let path = dir.path().join("profiles").join("a.json");
+        assert_eq!(read_file(&path), Ok(None));
+        write_file(&path, b"one").unwrap();
+        write_file(&path, b"two").unwrap();
+        assert_eq!(read_file(&path), Ok(Some(b"two".to_vec())));
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn unwritable_path_fails_with_the_path_in_the_message() {
+        let dir = TempDir::new();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        // A regular file where a directory is needed fails even for root.
+        let err = write_file(&file.join("a.json"), b"data").unwrap_err();
+        assert!(err.starts_with("Cannot create directory"), "{err}");
+        assert!(err.contains(&file.display().to_string()), "{err}");
+    }
+
+    #[test]
+    fn failed_replace_removes_the_temporary_file() {
+        let dir = TempDir::new();
+        let target = dir.path().join("taken");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("inside"), b"").unwrap();
+        let err = write_file(&target, b"data").unwrap_err();
+        assert!(err.starts_with("Cannot replace"), "{err}");
+        assert!(!target.with_extension("tmp").exists());
+    }
+}
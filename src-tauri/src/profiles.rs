@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use tauri::AppHandle;
+
+use crate::paths;
+use crate::ServerConfig;
+
+const PROFILES_FILE: &str = "profiles.json";
+
+pub struct ProfileStore {
+    path: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        Ok(Self {
+            path: paths::app_data_file(app, PROFILES_FILE)?,
+        })
+    }
+
+    pub fn save(&self, name: &str, config: ServerConfig) -> Result<(), String> {
+        let mut profiles = self.read_all()?;
+        profiles.insert(name.to_string(), config);
+        self.write_all(&profiles)
+    }
+
+    pub fn load(&self, name: &str) -> Result<ServerConfig, String> {
+        self.read_all()?
+            .remove(name)
+            .ok_or_else(|| format!("Profile \"{name}\" does not exist"))
+    }
+
+    pub fn list(&self) -> Result<Vec<String>, String> {
+        Ok(self.read_all()?.into_keys().collect())
+    }
+
+    fn read_all(&self) -> Result<BTreeMap<String, ServerConfig>, String> {
+        let Some(data) = paths::read_file(&self.path)? else {
+            return Ok(BTreeMap::new());
+        };
+        serde_json::from_slice(&data)
+            .map_err(|err| format!("Cannot parse {}: {err}", self.path.display()))
+    }
+
+    fn write_all(&self, profiles: &BTreeMap<String, ServerConfig>) -> Result<(), String> {
+        let data = serde_json::to_vec_pretty(profiles).map_err(|err| err.to_string())?;
+        paths::write_file(&self.path, &data)
+    }
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// Opt-in deviations from the spec that reproduce specific real devices, configured as a
+/// bitset of the constants below.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ResponseQuirks(pub u32);
+
+impl ResponseQuirks {
+    /// FC01/FC02: the unused high bits of the last response byte are sent as 1 instead of 0.
+    pub const PAD_BITS_WITH_ONES: u32 = 1 << 0;
+    /// FC23: the read half is taken before the write is applied, so registers in both ranges
+    /// report their previous value instead of the one just written.
+    pub const READ_BEFORE_WRITE: u32 = 1 << 1;
+
+    pub fn contains(self, quirk: u32) -> bool {
+        self.0 & quirk != 0
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::modbus::{DataArea, ModbusStore};
+use crate::word_order::WordOrder;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Bool,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl FieldType {
+    fn width(self) -> usize {
+        match self {
+            FieldType::Bool | FieldType::U16 | FieldType::I16 => 1,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 2,
+        }
+    }
+}
+
+/// A named value in the register map. `bool` fields live in coils or discrete inputs, all
+/// other types in registers; 32-bit types span two registers in `word_order`, `abcd` if unset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    pub area: DataArea,
+    pub offset: u16,
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+    #[serde(default)]
+    pub word_order: Option<WordOrder>,
+    /// Multiplies the decoded number, which is then always reported as a float.
+    #[serde(default)]
+    pub scale: Option<f64>,
+}
+
+impl SchemaField {
+    fn range(&self) -> std::ops::Range<usize> {
+        let start = self.offset as usize;
+        start..start + self.kind.width()
+    }
+}
+
+/// Rejects duplicate names, types that do not fit their area, fields past the end of the
+/// store and fields sharing an address with another field in the same area.
+pub fn validate(fields: &[SchemaField], store: &ModbusStore) -> Result<(), String> {
+    for (index, field) in fields.iter().enumerate() {
+        let bit_area = matches!(field.area, DataArea::Coils | DataArea::DiscreteInputs);
+        if bit_area != (field.kind == FieldType::Bool) {
+            return Err(format!(
+                "Field {} has type {:?}, which does not fit {:?}",
+                field.name, field.kind, field.area
+            ));
+        }
+        if field.range().end > store.len(field.area) {
+            return Err(format!("Field {} is out of range", field.name));
+        }
+        for other in &fields[..index] {
+            if other.name == field.name {
+                return Err(format!("Field {} is defined twice", field.name));
+            }
+            let (a, b) = (field.range(), other.range());
+            if other.area == field.area && a.start < b.end && b.start < a.end {
+                return Err(format!("Fields {} and {} overlap", other.name, field.name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes every field from the same copy of the store. A field left out of range by a later
+/// resize fails the whole read.
+pub fn read(
+    fields: &[SchemaField],
+    store: &ModbusStore,
+) -> Result<BTreeMap<String, Value>, String> {
+    fields
+        .iter()
+        .map(|field| Ok((field.name.clone(), decode(field, store)?)))
+        .collect()
+}
+
+fn decode(field: &SchemaField, store: &ModbusStore) -> Result<Value, String> {
+    let words = store.values(field.area, field.offset as usize, field.kind.width());
+    if words.is_empty() {
+        return Err(format!("Field {} is out of range", field.name));
+    }
+    let order = field.word_order.unwrap_or_default();
+    let number = match field.kind {
+        FieldType::Bool => return Ok(Value::Bool(words[0] != 0)),
+        FieldType::U16 => words[0] as f64,
+        FieldType::I16 => words[0] as i16 as f64,
+        FieldType::U32 => order.decode_u32(words[0], words[1]) as f64,
+        FieldType::I32 => order.decode_i32(words[0], words[1]) as f64,
+        FieldType::F32 => order.decode_f32(words[0], words[1]) as f64,
+    };
+    let value = match field.scale {
+        Some(scale) => Number::from_f64(number * scale).map(Value::Number),
+        None if field.kind == FieldType::F32 => Number::from_f64(number).map(Value::Number),
+        None => Some(Value::from(number as i64)),
+    };
+    // NaN and infinities have no JSON representation.
+    Ok(value.unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn fields(value: Value) -> Vec<SchemaField> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn rejects_overlapping_fields() {
+        let store = ModbusStore::new(8);
+        let overlapping = fields(json!([
+            { "name": "a", "area": "holding", "offset": 0, "type": "u32" },
+            { "name": "b", "area": "holding", "offset": 1, "type": "u16" },
+        ]));
+        let err = validate(&overlapping, &store).unwrap_err();
+        assert_eq!(err, "Fields a and b overlap");
+        let other_area = fields(json!([
+            { "name": "a", "area": "holding", "offset": 0, "type": "u32" },
+            { "name": "b", "area": "input", "offset": 1, "type": "u16" },
+        ]));
+        assert!(validate(&other_area, &store).is_ok());
+    }
+
+    #[test]
+    fn rejects_fields_past_the_end() {
+        let store = ModbusStore::new(8);
+        let last = fields(json!([
+            { "name": "a", "area": "holding", "offset": 6, "type": "f32" },
+        ]));
+        assert!(validate(&last, &store).is_ok());
+        let past = fields(json!([
+            { "name": "a", "area": "holding", "offset": 7, "type": "f32" },
+        ]));
+        assert_eq!(
+            validate(&past, &store).unwrap_err(),
+            "Field a is out of range"
+        );
+    }
+
+    #[test]
+    fn rejects_types_that_do_not_fit_the_area() {
+        let store = ModbusStore::new(8);
+        let register_in_coils = fields(json!([
+            { "name": "a", "area": "coils", "offset": 0, "type": "u16" },
+        ]));
+        assert!(validate(&register_in_coils, &store).is_err());
+        let bool_in_registers = fields(json!([
+            { "name": "a", "area": "input", "offset": 0, "type": "bool" },
+        ]));
+        assert!(validate(&bool_in_registers, &store).is_err());
+    }
+
+    #[test]
+    fn decodes_scaled_values_as_floats() {
+        let mut store = ModbusStore::new(8);
+        store.write_values(DataArea::HoldingRegisters, 0, &[0xFFF6, 0x0001, 0x0002]);
+        let schema = fields(json!([
+            { "name": "temp", "area": "holding", "offset": 0, "type": "i16", "scale": 0.5 },
+            { "name": "raw", "area": "holding", "offset": 0, "type": "u16" },
+            { "name": "total", "area": "holding", "offset": 1, "type": "u32",
+              "word_order": "cdab", "scale": 10.0 },
+        ]));
+        let values = read(&schema, &store).unwrap();
+        assert_eq!(values["temp"], json!(-5.0));
+        assert_eq!(values["raw"], json!(65526));
+        assert_eq!(values["total"], json!(1310730.0));
+    }
+}
@@ -0,0 +1,316 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio_modbus::server::tcp::Server;
+use tokio_util::sync::CancellationToken;
+
+use crate::connections::ConnectionRegistry;
+use crate::modbus::{ConnectionLimitMode, ConnectionService, ModbusService};
+use crate::transport::{self, CoilPackingLog, ConnectionStream};
+
+/// Accepts connections on `listener` and serves each with a clone of `service` until `cancel`
+/// fires. `on_status_update` runs whenever a connection opens or closes, and `on_error` for
+/// every connection that ends with a decode or I/O error, except ones the server closed
+/// itself. Needs no Tauri app, so tests can run the server in-process.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    service: ModbusService,
+    connections: ConnectionRegistry,
+    on_status_update: Arc<dyn Fn() + Send + Sync>,
+    on_error: impl FnOnce(io::Error) + Clone + Send + 'static,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    let limiter = service
+        .options()
+        .max_connections
+        .map(|max| Arc::new(Semaphore::new(max)));
+    let on_connected = move |stream: TcpStream, socket_addr: SocketAddr| {
+        let service = service.clone();
+        let connections = connections.clone();
+        let on_status_update = on_status_update.clone();
+        let limiter = limiter.clone();
+        async move {
+            let permit = match limiter {
+                Some(limiter) => {
+                    let permit = match service.options().connection_limit_mode {
+                        ConnectionLimitMode::Queue => limiter.acquire_owned().await.ok(),
+                        ConnectionLimitMode::Reject => limiter.try_acquire_owned().ok(),
+                    };
+                    if permit.is_none() {
+                        service
+                            .activity()
+                            .warn("Connection limit reached, rejected", Some(socket_addr));
+                        return Ok(None);
+                    }
+                    permit
+                }
+                None => None,
+            };
+            let coil_packing = service
+                .options()
+                .strict_coil_packing
+                .then(CoilPackingLog::default);
+            let connection = connections.register(socket_addr, coil_packing);
+            (on_status_update)();
+            let stream = ConnectionStream::new(
+                stream,
+                connection.clone(),
+                service.metrics().clone(),
+                service.options().response_unit_id,
+                service.options().protocol_id_mode,
+                service
+                    .options()
+                    .read_timeout_ms
+                    .filter(|ms| *ms > 0)
+                    .map(Duration::from_millis),
+            );
+            Ok(Some((
+                ConnectionService::new(service, connection, connections, on_status_update, permit),
+                stream,
+            )))
+        }
+    };
+    let on_error = move |err: io::Error| {
+        if !transport::is_closed_by_server(&err) {
+            on_error(err);
+        }
+    };
+    let abort_signal = async move {
+        cancel.cancelled().await;
+    };
+    Server::new(listener)
+        .serve_until(&on_connected, on_error, abort_signal)
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio_modbus::prelude::*;
+
+    use crate::harness::TestServer;
+    use crate::modbus::{DataArea, ModbusStore, ServiceOptions};
+
+    const UNIT: u8 = 1;
+
+    fn seeded_store() -> ModbusStore {
+        let mut store = ModbusStore::new(16);
+        store.write_values(DataArea::Coils, 0, &[1, 0, 1]);
+        store.write_values(DataArea::DiscreteInputs, 0, &[0, 1, 1]);
+        store.write_values(DataArea::InputRegisters, 0, &[10, 20, 30]);
+        store.write_values(DataArea::HoldingRegisters, 0, &[100, 200, 300]);
+        store
+    }
+
+    async fn start() -> TestServer {
+        TestServer::start(seeded_store(), UNIT, ServiceOptions::default()).await
+    }
+
+    async fn until_disconnected(server: &TestServer) {
+        for _ in 0..100 {
+            if server.connections.count() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.connections.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn reads_every_area() {
+        let server = start().await;
+        let mut client = server.client(UNIT).await;
+        let coils = client.read_coils(0, 4).await.unwrap().unwrap();
+        assert_eq!(coils, vec![true, false, true, false]);
+        let inputs = client.read_discrete_inputs(0, 3).await.unwrap().unwrap();
+        assert_eq!(inputs, vec![false, true, true]);
+        let words = client.read_input_registers(1, 2).await.unwrap().unwrap();
+        assert_eq!(words, vec![20, 30]);
+        let words = client.read_holding_registers(0, 3).await.unwrap().unwrap();
+        assert_eq!(words, vec![100, 200, 300]);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn writes_reach_the_store_and_read_back() {
+        let server = start().await;
+        let mut client = server.client(UNIT).await;
+        client.write_single_coil(5, true).await.unwrap().unwrap();
+        client
+            .write_multiple_coils(8, &[true, true, false, true])
+            .await
+            .unwrap()
+            .unwrap();
+        client
+            .write_single_register(6, 0xBEEF)
+            .await
+            .unwrap()
+            .unwrap();
+        client
+            .write_multiple_registers(10, &[1, 2, 3])
+            .await
+            .unwrap()
+            .unwrap();
+
+        let coils = client.read_coils(5, 7).await.unwrap().unwrap();
+        assert_eq!(coils, vec![true, false, false, true, true, false, true]);
+        let words = client.read_holding_registers(6, 7).await.unwrap().unwrap();
+        assert_eq!(words, vec![0xBEEF, 0, 0, 0, 1, 2, 3]);
+
+        let store = server.store.read().unwrap();
+        assert_eq!(store.values(DataArea::Coils, 8, 4), vec![1, 1, 0, 1]);
+        assert_eq!(
+            store.values(DataArea::HoldingRegisters, 10, 3),
+            vec![1, 2, 3]
+        );
+        drop(store);
+        let updates = server.sink.updates();
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[3].area, DataArea::HoldingRegisters);
+        assert_eq!(
+            (updates[3].offset, updates[3].values.clone()),
+            (10, vec![1, 2, 3])
+        );
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn mask_write_and_read_write_multiple() {
+        let server = start().await;
+        let mut client = server.client(UNIT).await;
+        client
+            .masked_write_register(2, 0xFF00, 0x0005)
+            .await
+            .unwrap()
+            .unwrap();
+        let words = client.read_holding_registers(2, 1).await.unwrap().unwrap();
+        assert_eq!(words, vec![0x0105]);
+
+        let words = client
+            .read_write_multiple_registers(3, 3, 4, &[7, 8])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(words, vec![0, 7, 8]);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn report_server_id_reports_running() {
+        let server = start().await;
+        let mut client = server.client(UNIT).await;
+        let response = client.call(Request::ReportServerId).await.unwrap().unwrap();
+        assert_eq!(response, Response::ReportServerId(UNIT, true, Vec::new()));
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn out_of_range_requests_get_exceptions() {
+        let server = start().await;
+        let mut client = server.client(UNIT).await;
+        let result = client.read_holding_registers(15, 2).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        let result = client.write_single_coil(16, true).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        let logged = server.sink.events("modbus://log");
+        let failures = logged
+            .iter()
+            .filter(|entry| entry["level"] == "warn")
+            .count();
+        assert_eq!(failures, 2);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn tracks_connections_until_the_client_leaves() {
+        let server = start().await;
+        let mut client = server.client(UNIT).await;
+        client.read_coils(0, 1).await.unwrap().unwrap();
+        assert_eq!(server.connections.count(), 1);
+        let metrics = server.metrics.snapshot();
+        assert_eq!((metrics.bytes_in, metrics.bytes_out), (12, 10));
+        drop(client);
+        until_disconnected(&server).await;
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn pipelined_requests_are_answered_in_order() {
+        let server = start().await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        let mut frames = Vec::new();
+        for transaction in 0..32u16 {
+            let [hi, lo] = transaction.to_be_bytes();
+            let pdu = if transaction % 2 == 0 {
+                [0x03, 0, 0, 0, 3]
+            } else {
+                [0x06, 0, 8, hi, lo]
+            };
+            frames.extend_from_slice(&[hi, lo, 0, 0, 0, 6, UNIT]);
+            frames.extend_from_slice(&pdu);
+        }
+        stream.write_all(&frames).await.unwrap();
+
+        for transaction in 0..32u16 {
+            let mut header = [0u8; 7];
+            stream.read_exact(&mut header).await.unwrap();
+            assert_eq!(u16::from_be_bytes([header[0], header[1]]), transaction);
+            let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+            let mut pdu = vec![0u8; len - 1];
+            stream.read_exact(&mut pdu).await.unwrap();
+            let expected = if transaction % 2 == 0 { 0x03 } else { 0x06 };
+            assert_eq!(pdu[0], expected);
+        }
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn stalled_partial_request_is_closed_quietly() {
+        let options = ServiceOptions {
+            read_timeout_ms: Some(100),
+            ..ServiceOptions::default()
+        };
+        let server = TestServer::start(seeded_store(), UNIT, options).await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        stream
+            .write_all(&[0, 1, 0, 0, 0, 6, UNIT, 0x03, 0])
+            .await
+            .unwrap();
+        let mut received = [0u8; 12];
+        assert_eq!(stream.read(&mut received).await.unwrap(), 0);
+        until_disconnected(&server).await;
+        // The error callback runs just after the connection is dropped.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(server.metrics.snapshot().read_timeouts, 1);
+        assert_eq!(server.errors(), Vec::<String>::new());
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn split_header_with_a_protocol_id_is_rejected_quietly() {
+        let server = start().await;
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        stream.write_all(&[0, 1, 0]).await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stream
+            .write_all(&[1, 0, 6, UNIT, 0x03, 0, 0, 0, 1])
+            .await
+            .unwrap();
+        let mut received = [0u8; 12];
+        assert_eq!(stream.read(&mut received).await.unwrap(), 0);
+        until_disconnected(&server).await;
+        // The error callback runs just after the connection is dropped.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(server.errors(), Vec::<String>::new());
+        server.stop().await;
+    }
+}
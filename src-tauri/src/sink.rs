@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::modbus::UpdatePayload;
+use crate::updates::UpdateQueue;
+
+/// Where the Modbus service sends its events. The app handle forwards them to the webview;
+/// any other implementation lets the service run without a Tauri app.
+pub trait UpdateSink: Send + Sync {
+    /// A changed run of addresses, sent as `modbus://updated`.
+    fn emit_update(&self, payload: UpdatePayload);
+
+    /// Any other event, such as `modbus://log` or `modbus://denied`.
+    fn emit_event(&self, event: &str, payload: Value);
+}
+
+impl dyn UpdateSink {
+    pub fn send<T: Serialize>(&self, event: &str, payload: T) {
+        if let Ok(payload) = serde_json::to_value(payload) {
+            self.emit_event(event, payload);
+        }
+    }
+}
+
+impl UpdateSink for AppHandle {
+    fn emit_update(&self, payload: UpdatePayload) {
+        match self.try_state::<Arc<UpdateQueue>>() {
+            Some(queue) => queue.emit(self, payload),
+            None => {
+                let _ = self.emit("modbus://updated", payload);
+            }
+        }
+    }
+
+    fn emit_event(&self, event: &str, payload: Value) {
+        let _ = self.emit(event, payload);
+    }
+}
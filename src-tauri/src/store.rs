@@ -0,0 +1,442 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{
+    Arc, LockResult, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+use std::time::Duration;
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use tokio::sync::watch;
+
+use crate::debounce::Debouncer;
+use crate::freeze::{BufferedWrite, FreezeWritePolicy};
+use crate::modbus::{DataArea, ModbusStore, ServiceOptions};
+use crate::overrides::ReadOverrides;
+use crate::sink::UpdateSink;
+
+pub struct SharedStore {
+    live: RwLock<ModbusStore>,
+    snapshot: ArcSwap<ModbusStore>,
+    /// Set when a write was released without publishing `snapshot`; `view()` catches up.
+    stale: AtomicBool,
+    snapshots_enabled: AtomicBool,
+    diff_updates: AtomicBool,
+    read_only_locked: AtomicBool,
+    frozen: ArcSwapOption<ModbusStore>,
+    frozen_policy: AtomicU8,
+    default_freeze_policy: AtomicU8,
+    buffered: Mutex<Vec<BufferedWrite>>,
+    revision: AtomicU64,
+    overrides: ReadOverrides,
+    debouncer: ArcSwapOption<Debouncer>,
+    changes: watch::Sender<u64>,
+}
+
+impl SharedStore {
+    pub fn new(store: ModbusStore) -> Self {
+        Self {
+            snapshot: ArcSwap::from_pointee(store.clone()),
+            live: RwLock::new(store),
+            stale: AtomicBool::new(false),
+            snapshots_enabled: AtomicBool::new(false),
+            diff_updates: AtomicBool::new(false),
+            read_only_locked: AtomicBool::new(false),
+            frozen: ArcSwapOption::empty(),
+            frozen_policy: AtomicU8::new(0),
+            default_freeze_policy: AtomicU8::new(0),
+            buffered: Mutex::new(Vec::new()),
+            revision: AtomicU64::new(0),
+            overrides: ReadOverrides::default(),
+            debouncer: ArcSwapOption::empty(),
+            changes: watch::Sender::new(0),
+        }
+    }
+
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, ModbusStore>> {
+        self.live.read()
+    }
+
+    pub fn write(
+        &self,
+    ) -> Result<StoreWriteGuard<'_>, PoisonError<RwLockWriteGuard<'_, ModbusStore>>> {
+        let guard = self.live.write()?;
+        Ok(StoreWriteGuard {
+            guard,
+            shared: self,
+            modified: false,
+        })
+    }
+
+    /// Latest published copy of the live store for local commands; never waits on a writer,
+    /// which may still be holding the lock for a bulk update that is not visible yet. Writes
+    /// released while nothing needed them published are copied in here, unless a writer
+    /// holds the lock again, in which case the last published copy is returned.
+    pub fn view(&self) -> Arc<ModbusStore> {
+        if self.stale.swap(false, Ordering::SeqCst) {
+            match self.live.try_read() {
+                Ok(store) => self.snapshot.store(Arc::new(ModbusStore::clone(&store))),
+                Err(_) => self.stale.store(true, Ordering::SeqCst),
+            }
+        }
+        self.snapshot.load_full()
+    }
+
+    /// Whether a released write must publish `snapshot` right away: masters read from it,
+    /// `diff_updates` compares against it, or a `subscribe` receiver expects it current.
+    fn publishes_eagerly(&self) -> bool {
+        self.snapshots_enabled.load(Ordering::SeqCst)
+            || self.diff_updates()
+            || self.changes.receiver_count() > 0
+    }
+
+    pub fn load(
+        &self,
+    ) -> Result<StoreReadGuard<'_>, PoisonError<RwLockReadGuard<'_, ModbusStore>>> {
+        if let Some(frozen) = self.frozen.load_full() {
+            return Ok(StoreReadGuard::Snapshot(frozen));
+        }
+        if self.snapshots_enabled.load(Ordering::SeqCst) {
+            return Ok(StoreReadGuard::Snapshot(self.view()));
+        }
+        Ok(StoreReadGuard::Locked(self.live.read()?))
+    }
+
+    /// Applies the store-level settings of a server's `options`; the areas themselves are
+    /// configured separately, under the write lock.
+    pub fn configure(&self, options: &ServiceOptions, sink: Arc<dyn UpdateSink>) {
+        self.set_snapshots(options.snapshot_reads);
+        self.set_diff_updates(options.diff_updates);
+        self.set_read_only_locked(options.lock_read_only_areas);
+        self.set_freeze_policy(options.freeze_write_policy);
+        let debounce = options.debounce_ms.filter(|ms| *ms > 0);
+        self.set_debounce(debounce.map(Duration::from_millis), sink);
+    }
+
+    pub fn set_snapshots(&self, enabled: bool) {
+        self.snapshots_enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn set_diff_updates(&self, enabled: bool) {
+        self.diff_updates.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn diff_updates(&self) -> bool {
+        self.diff_updates.load(Ordering::SeqCst)
+    }
+
+    pub fn set_read_only_locked(&self, locked: bool) {
+        self.read_only_locked.store(locked, Ordering::SeqCst);
+    }
+
+    /// Whether local commands may write `area`. Masters can never write discrete inputs or
+    /// input registers; with the lock on, local commands cannot either.
+    pub fn locally_writable(&self, area: DataArea) -> bool {
+        let read_only = matches!(area, DataArea::DiscreteInputs | DataArea::InputRegisters);
+        !(read_only && self.read_only_locked.load(Ordering::SeqCst))
+    }
+
+    pub fn set_debounce(&self, interval: Option<Duration>, sink: Arc<dyn UpdateSink>) {
+        self.debouncer
+            .store(interval.map(|interval| Arc::new(Debouncer::new(interval, sink))));
+    }
+
+    pub fn debouncer(&self) -> Option<Arc<Debouncer>> {
+        self.debouncer.load_full()
+    }
+
+    /// The policy `freeze` uses when the caller does not pick one.
+    pub fn set_freeze_policy(&self, policy: FreezeWritePolicy) {
+        self.default_freeze_policy.store(policy.to_u8(), Ordering::SeqCst);
+    }
+
+    pub fn freeze_policy(&self) -> FreezeWritePolicy {
+        FreezeWritePolicy::from_u8(self.default_freeze_policy.load(Ordering::SeqCst))
+    }
+
+    /// Pins the view masters read. Freezing again while frozen re-pins the view and switches
+    /// the policy, keeping any writes already buffered.
+    pub fn freeze(&self, policy: FreezeWritePolicy) -> bool {
+        let Ok(store) = self.live.read() else {
+            return false;
+        };
+        self.frozen_policy.store(policy.to_u8(), Ordering::SeqCst);
+        self.frozen
+            .store(Some(Arc::new(ModbusStore::clone(&store))));
+        true
+    }
+
+    /// Releases the frozen view, restoring it to the live store unless `keep_edits`, then
+    /// applies the writes buffered meanwhile. Returns how many buffered writes were applied,
+    /// or `None` if the store was not frozen.
+    pub fn unfreeze(&self, keep_edits: bool) -> Option<usize> {
+        let mut queue = self
+            .buffered
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let frozen = self.frozen.swap(None)?;
+        self.frozen_policy.store(0, Ordering::SeqCst);
+        let buffered = std::mem::take(&mut *queue);
+        drop(queue);
+        if keep_edits && buffered.is_empty() {
+            return Some(0);
+        }
+        let Ok(mut store) = self.write() else {
+            return Some(0);
+        };
+        if !keep_edits && store.restore(&frozen).is_err() {
+            *store = ModbusStore::clone(&frozen);
+        }
+        Some(buffered.iter().filter(|write| write.apply(&mut store)).count())
+    }
+
+    /// Policy of the current freeze, or `None` while not frozen.
+    pub fn frozen_policy(&self) -> Option<FreezeWritePolicy> {
+        if self.frozen.load().is_none() {
+            return None;
+        }
+        Some(FreezeWritePolicy::from_u8(self.frozen_policy.load(Ordering::SeqCst)))
+    }
+
+    /// Queues `write` for unfreeze. Fails if the store was unfrozen after the caller checked
+    /// `frozen_policy`, as the queue has already been applied by then.
+    pub fn buffer_write(&self, write: BufferedWrite) -> bool {
+        let mut buffered = self
+            .buffered
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if self.frozen.load().is_none() {
+            return false;
+        }
+        buffered.push(write);
+        true
+    }
+
+    pub fn frozen(&self) -> Option<Arc<ModbusStore>> {
+        self.frozen.load_full()
+    }
+
+    /// Bumped on every release of a write guard that modified the store, so it only changes
+    /// while the write lock is still held and is consistent with the contents seen under a read
+    /// lock.
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    /// Receives the new revision after every write guard release that modified the store, once
+    /// `view()` shows the written values.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    pub fn overrides(&self) -> &ReadOverrides {
+        &self.overrides
+    }
+}
+
+pub enum StoreReadGuard<'a> {
+    Locked(RwLockReadGuard<'a, ModbusStore>),
+    Snapshot(Arc<ModbusStore>),
+}
+
+impl Deref for StoreReadGuard<'_> {
+    type Target = ModbusStore;
+
+    fn deref(&self) -> &ModbusStore {
+        match self {
+            StoreReadGuard::Locked(guard) => &**guard,
+            StoreReadGuard::Snapshot(store) => &**store,
+        }
+    }
+}
+
+/// Write access to the live store. Only a guard that was mutably dereferenced counts as a
+/// write on release, so validate through `Deref` first and a refused write leaves the
+/// revision and the published view alone.
+pub struct StoreWriteGuard<'a> {
+    guard: RwLockWriteGuard<'a, ModbusStore>,
+    shared: &'a SharedStore,
+    modified: bool,
+}
+
+impl Deref for StoreWriteGuard<'_> {
+    type Target = ModbusStore;
+
+    fn deref(&self) -> &ModbusStore {
+        &self.guard
+    }
+}
+
+impl DerefMut for StoreWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut ModbusStore {
+        self.modified = true;
+        &mut self.guard
+    }
+}
+
+impl Drop for StoreWriteGuard<'_> {
+    fn drop(&mut self) {
+        if !self.modified {
+            return;
+        }
+        let shared = self.shared;
+        let revision = shared.revision.fetch_add(1, Ordering::SeqCst) + 1;
+        if shared.publishes_eagerly() {
+            shared
+                .snapshot
+                .store(Arc::new(ModbusStore::clone(&self.guard)));
+            shared.stale.store(false, Ordering::SeqCst);
+        } else {
+            shared.stale.store(true, Ordering::SeqCst);
+        }
+        shared.changes.send_replace(revision);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use tokio_modbus::prelude::*;
+
+    use super::*;
+    use crate::harness::TestServer;
+    use crate::modbus::ServiceOptions;
+
+    #[test]
+    fn unmodified_guard_is_not_a_write() {
+        let shared = SharedStore::new(ModbusStore::new(8));
+        let changes = shared.subscribe();
+        let store = shared.write().unwrap();
+        assert_eq!(store.len(DataArea::HoldingRegisters), 8);
+        drop(store);
+        assert_eq!(shared.revision(), 0);
+        assert!(!changes.has_changed().unwrap());
+    }
+
+    #[test]
+    fn view_catches_up_with_writes_nothing_published() {
+        let shared = SharedStore::new(ModbusStore::new(8));
+        let before = shared.view();
+        shared
+            .write()
+            .unwrap()
+            .write_values(DataArea::HoldingRegisters, 2, &[7]);
+        assert_eq!(shared.revision(), 1);
+        assert_eq!(before.values(DataArea::HoldingRegisters, 2, 1), vec![0]);
+        assert_eq!(
+            shared.view().values(DataArea::HoldingRegisters, 2, 1),
+            vec![7]
+        );
+    }
+
+    #[test]
+    fn subscribers_see_the_write_in_view() {
+        let shared = SharedStore::new(ModbusStore::new(8));
+        let mut changes = shared.subscribe();
+        let mut store = shared.write().unwrap();
+        store.write_values(DataArea::Coils, 0, &[1]);
+        drop(store);
+        assert_eq!(*changes.borrow_and_update(), 1);
+        // Published on release rather than on the next view(), so no refresh is pending.
+        assert!(!shared.stale.load(Ordering::SeqCst));
+        assert_eq!(shared.view().values(DataArea::Coils, 0, 1), vec![1]);
+    }
+
+    #[test]
+    fn view_does_not_wait_for_a_large_range_write() {
+        let shared = Arc::new(SharedStore::new(ModbusStore::new(4096)));
+        shared.set_snapshots(true);
+        let mut store = shared.write().unwrap();
+        store.write_values(DataArea::HoldingRegisters, 0, &[0xAAAA; 4096]);
+
+        let (done_tx, done_rx) = mpsc::channel();
+        let reader = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let view = shared.view();
+                done_tx
+                    .send(view.values(DataArea::HoldingRegisters, 0, 4096))
+                    .unwrap();
+            })
+        };
+        let seen = done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("view() blocked on the write lock");
+        assert!(seen.iter().all(|value| *value == 0));
+        drop(store);
+        reader.join().unwrap();
+        let view = shared.view();
+        assert!(view
+            .values(DataArea::HoldingRegisters, 0, 4096)
+            .iter()
+            .all(|value| *value == 0xAAAA));
+    }
+
+    #[tokio::test]
+    async fn refused_master_write_keeps_the_revision() {
+        let server = TestServer::start(ModbusStore::new(8), 1, ServiceOptions::default()).await;
+        let mut client = server.client(1).await;
+        let result = client
+            .write_multiple_registers(6, &[1, 2, 3])
+            .await
+            .unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+        assert_eq!(server.store.revision(), 0);
+        client.write_single_register(6, 1).await.unwrap().unwrap();
+        assert_eq!(server.store.revision(), 1);
+        server.stop().await;
+    }
+
+    /// Reads served in `window` by four readers of `load()` while a writer keeps rewriting
+    /// a 1024-register range.
+    fn reads_under_writes(snapshots: bool, window: Duration) -> u64 {
+        let shared = Arc::new(SharedStore::new(ModbusStore::new(1024)));
+        shared.set_snapshots(snapshots);
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (shared, stop) = (shared.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut value = 0u16;
+                while !stop.load(Ordering::Relaxed) {
+                    value = value.wrapping_add(1);
+                    let mut store = shared.write().unwrap();
+                    store.write_values(DataArea::HoldingRegisters, 0, &[value; 1024]);
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let (shared, stop) = (shared.clone(), stop.clone());
+                thread::spawn(move || {
+                    let mut reads = 0u64;
+                    while !stop.load(Ordering::Relaxed) {
+                        let store = shared.load().unwrap();
+                        assert_eq!(store.values(DataArea::HoldingRegisters, 0, 8).len(), 8);
+                        reads += 1;
+                    }
+                    reads
+                })
+            })
+            .collect();
+        thread::sleep(window);
+        stop.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+        readers
+            .into_iter()
+            .map(|reader| reader.join().unwrap())
+            .sum()
+    }
+
+    /// Compares the RwLock and snapshot read paths; run with
+    /// `cargo test --release -- --ignored --nocapture compare_read_paths`.
+    #[test]
+    #[ignore]
+    fn compare_read_paths_under_writes() {
+        let window = Duration::from_secs(2);
+        let locked = reads_under_writes(false, window);
+        let snapshot = reads_under_writes(true, window);
+        println!("reads in {window:?}: rwlock {locked}, snapshot {snapshot}");
+        assert!(locked > 0 && snapshot > 0);
+    }
+}
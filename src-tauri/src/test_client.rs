@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::net::SocketAddr;
+
+use tokio_modbus::client::{tcp, Context, Reader, Writer};
+use tokio_modbus::slave::Slave;
+
+pub use crate::word_order::WordOrder;
+
+pub type ClientResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Thin wrapper over the `tokio-modbus` TCP client covering the function codes the server
+/// supports. Transport errors and Modbus exceptions are both surfaced as errors.
+pub struct TestClient {
+    context: Context,
+}
+
+impl TestClient {
+    pub async fn connect(addr: SocketAddr, unit: u8) -> ClientResult<Self> {
+        let context = tcp::connect_slave(addr, Slave(unit)).await?;
+        Ok(Self { context })
+    }
+
+    pub async fn read_coils(&mut self, addr: u16, qty: u16) -> ClientResult<Vec<bool>> {
+        Ok(self.context.read_coils(addr, qty).await??)
+    }
+
+    pub async fn read_discrete_inputs(&mut self, addr: u16, qty: u16) -> ClientResult<Vec<bool>> {
+        Ok(self.context.read_discrete_inputs(addr, qty).await??)
+    }
+
+    pub async fn read_input_registers(&mut self, addr: u16, qty: u16) -> ClientResult<Vec<u16>> {
+        Ok(self.context.read_input_registers(addr, qty).await??)
+    }
+
+    pub async fn read_holding_registers(
+        &mut self,
+        addr: u16,
+        qty: u16,
+    ) -> ClientResult<Vec<u16>> {
+        Ok(self.context.read_holding_registers(addr, qty).await??)
+    }
+
+    pub async fn write_single_coil(&mut self, addr: u16, value: bool) -> ClientResult<()> {
+        Ok(self.context.write_single_coil(addr, value).await??)
+    }
+
+    pub async fn write_multiple_coils(&mut self, addr: u16, values: &[bool]) -> ClientResult<()> {
+        Ok(self.context.write_multiple_coils(addr, values).await??)
+    }
+
+    pub async fn write_single_register(&mut self, addr: u16, value: u16) -> ClientResult<()> {
+        Ok(self.context.write_single_register(addr, value).await??)
+    }
+
+    pub async fn write_multiple_registers(
+        &mut self,
+        addr: u16,
+        values: &[u16],
+    ) -> ClientResult<()> {
+        Ok(self.context.write_multiple_registers(addr, values).await??)
+    }
+
+    pub async fn masked_write_register(
+        &mut self,
+        addr: u16,
+        and_mask: u16,
+        or_mask: u16,
+    ) -> ClientResult<()> {
+        Ok(self
+            .context
+            .masked_write_register(addr, and_mask, or_mask)
+            .await??)
+    }
+
+    pub async fn read_write_multiple_registers(
+        &mut self,
+        read_addr: u16,
+        read_qty: u16,
+        write_addr: u16,
+        values: &[u16],
+    ) -> ClientResult<Vec<u16>> {
+        Ok(self
+            .context
+            .read_write_multiple_registers(read_addr, read_qty, write_addr, values)
+            .await??)
+    }
+
+    pub async fn read_input_f32(&mut self, addr: u16, order: WordOrder) -> ClientResult<f32> {
+        let words = self.read_input_registers(addr, 2).await?;
+        Ok(order.decode_f32(words[0], words[1]))
+    }
+
+    pub async fn read_holding_f32(&mut self, addr: u16, order: WordOrder) -> ClientResult<f32> {
+        let words = self.read_holding_registers(addr, 2).await?;
+        Ok(order.decode_f32(words[0], words[1]))
+    }
+
+    pub async fn read_holding_u32(&mut self, addr: u16, order: WordOrder) -> ClientResult<u32> {
+        let words = self.read_holding_registers(addr, 2).await?;
+        Ok(order.decode_u32(words[0], words[1]))
+    }
+
+    pub async fn read_holding_i32(&mut self, addr: u16, order: WordOrder) -> ClientResult<i32> {
+        let words = self.read_holding_registers(addr, 2).await?;
+        Ok(order.decode_i32(words[0], words[1]))
+    }
+}
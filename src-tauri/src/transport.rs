@@ -0,0 +1,445 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+use crate::connections::ConnectionEntry;
+use crate::metrics::ServerMetrics;
+
+const MBAP_HEADER_LEN: usize = 7;
+const MBAP_PROTOCOL_ID: usize = 2;
+const MBAP_UNIT_ID: usize = 6;
+const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+
+struct CoilPacking {
+    addr: u16,
+    qty: u16,
+    valid: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct CoilPackingLog {
+    checks: Arc<Mutex<VecDeque<CoilPacking>>>,
+}
+
+impl CoilPackingLog {
+    fn record(&self, check: CoilPacking) {
+        if let Ok(mut checks) = self.checks.lock() {
+            checks.push_back(check);
+        }
+    }
+
+    pub fn take(&self, addr: u16, qty: u16) -> Option<bool> {
+        let mut checks = self.checks.lock().ok()?;
+        while let Some(check) = checks.pop_front() {
+            if check.addr == addr && check.qty == qty {
+                return Some(check.valid);
+            }
+        }
+        None
+    }
+}
+
+/// What happens to a request whose MBAP protocol identifier is not 0, the value for Modbus.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolIdMode {
+    /// The connection is closed without an answer, as conformance testers expect.
+    #[default]
+    Reject,
+    /// The request is served and its response carries the same protocol identifier, for
+    /// gateways that tunnel other protocols over MBAP.
+    Echo,
+}
+
+/// Error a connection ends with when the server closed it on purpose, e.g. after a read
+/// timeout. The codec may hold part of a request by then, which a plain end of stream would
+/// report as a decode failure.
+#[derive(Debug)]
+struct ClosedByServer;
+
+impl fmt::Display for ClosedByServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("connection closed by the server")
+    }
+}
+
+impl Error for ClosedByServer {}
+
+fn closed_by_server() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, ClosedByServer)
+}
+
+/// Whether `err` ended a connection the server closed itself, which is not a fault.
+pub(crate) fn is_closed_by_server(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<ClosedByServer>())
+}
+
+/// Position within a byte stream of MBAP frames, used to find the header fields of each frame
+/// even when a frame is split across reads or writes.
+#[derive(Clone, Copy, Default)]
+struct FrameCursor {
+    pos: usize,
+    transaction: u16,
+    protocol: u16,
+    len_high: u8,
+    frame_len: usize,
+}
+
+impl FrameCursor {
+    /// Walks outgoing `data` from the current position, rewriting the header of every response:
+    /// the protocol id is the one recorded for its transaction, if any, and the unit id `unit`.
+    fn patch(mut self, data: &mut [u8], unit: Option<u8>, protocols: &HashMap<u16, u16>) {
+        for byte in data {
+            match self.pos {
+                MBAP_PROTOCOL_ID | 3 => {
+                    if let Some(protocol) = protocols.get(&self.transaction) {
+                        *byte = protocol.to_be_bytes()[self.pos - MBAP_PROTOCOL_ID];
+                    }
+                }
+                MBAP_UNIT_ID => {
+                    if let Some(unit) = unit {
+                        *byte = unit;
+                    }
+                }
+                _ => {}
+            }
+            self.step(*byte);
+        }
+    }
+
+    /// Moves past written `data`, forgetting the protocol id of every response sent.
+    fn advance(&mut self, data: &[u8], protocols: &mut HashMap<u16, u16>) {
+        for byte in data {
+            self.step(*byte);
+            if self.pos == MBAP_PROTOCOL_ID + 2 {
+                protocols.remove(&self.transaction);
+            }
+        }
+    }
+
+    fn step(&mut self, byte: u8) {
+        match self.pos {
+            0 => self.transaction = u16::from(byte) << 8,
+            1 => self.transaction |= u16::from(byte),
+            2 => self.protocol = u16::from(byte) << 8,
+            3 => self.protocol |= u16::from(byte),
+            4 => self.len_high = byte,
+            5 => self.frame_len = 6 + u16::from_be_bytes([self.len_high, byte]) as usize,
+            _ => {}
+        }
+        self.pos += 1;
+        if self.pos > 5 && self.pos >= self.frame_len {
+            self.pos = 0;
+        }
+    }
+}
+
+pub struct ConnectionStream<S> {
+    inner: S,
+    pending: Vec<u8>,
+    connection: Arc<ConnectionEntry>,
+    metrics: Arc<ServerMetrics>,
+    response_unit_id: Option<u8>,
+    protocol_ids: ProtocolIdMode,
+    /// Non-zero protocol ids of requests still awaiting a response, by transaction id.
+    protocols: HashMap<u16, u16>,
+    in_cursor: FrameCursor,
+    out_cursor: FrameCursor,
+    read_timeout: Option<Duration>,
+    /// Armed by the first byte of a request and cleared once the request is complete.
+    frame_deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ConnectionStream<S> {
+    pub fn new(
+        inner: S,
+        connection: Arc<ConnectionEntry>,
+        metrics: Arc<ServerMetrics>,
+        response_unit_id: Option<u8>,
+        protocol_ids: ProtocolIdMode,
+        read_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            connection,
+            metrics,
+            response_unit_id,
+            protocol_ids,
+            protocols: HashMap::new(),
+            in_cursor: FrameCursor::default(),
+            out_cursor: FrameCursor::default(),
+            read_timeout,
+            frame_deadline: None,
+        }
+    }
+
+    /// Starts the read timeout when a read leaves a request incomplete, and stops it once
+    /// every request read so far is complete. Time spent between requests is not limited.
+    fn track_frame(&mut self) {
+        let Some(timeout) = self.read_timeout else {
+            return;
+        };
+        if self.in_cursor.pos == 0 {
+            self.frame_deadline = None;
+        } else if self.frame_deadline.is_none() {
+            self.frame_deadline = Some(Box::pin(tokio::time::sleep(timeout)));
+        }
+    }
+
+    /// Checks the protocol id of every request in `data`. With `Echo`, non-zero ids are
+    /// recorded and cleared so the codec accepts the frame; with `Reject`, fails as soon as one
+    /// is seen with the offset in `data` where that request starts, 0 if it began earlier.
+    fn screen_protocol_ids(&mut self, data: &mut [u8]) -> Result<(), usize> {
+        let mut frame_start = 0;
+        for (index, byte) in data.iter_mut().enumerate() {
+            let pos = self.in_cursor.pos;
+            if pos == 0 {
+                frame_start = index;
+            }
+            self.in_cursor.step(*byte);
+            if pos != MBAP_PROTOCOL_ID && pos != MBAP_PROTOCOL_ID + 1 {
+                continue;
+            }
+            if self.protocol_ids == ProtocolIdMode::Echo {
+                *byte = 0;
+            }
+            if pos == MBAP_PROTOCOL_ID + 1 && self.in_cursor.protocol != 0 {
+                match self.protocol_ids {
+                    ProtocolIdMode::Reject => return Err(frame_start),
+                    ProtocolIdMode::Echo => {
+                        let cursor = self.in_cursor;
+                        self.protocols.insert(cursor.transaction, cursor.protocol);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn record_read(&mut self, data: &[u8]) {
+        self.connection.traffic.record_in(data.len());
+        self.metrics.traffic.record_in(data.len());
+        let Some(coil_packing) = &self.connection.coil_packing else {
+            return;
+        };
+        self.pending.extend_from_slice(data);
+        while self.pending.len() >= MBAP_HEADER_LEN {
+            let len = u16::from_be_bytes([self.pending[4], self.pending[5]]) as usize;
+            let frame_len = 6 + len.max(1);
+            if self.pending.len() < frame_len {
+                break;
+            }
+            let frame: Vec<u8> = self.pending.drain(..frame_len).collect();
+            if let Some(check) = check_coil_packing(&frame[MBAP_HEADER_LEN..]) {
+                coil_packing.record(check);
+            }
+        }
+    }
+}
+
+fn check_coil_packing(pdu: &[u8]) -> Option<CoilPacking> {
+    if pdu.len() < 6 || pdu[0] != WRITE_MULTIPLE_COILS {
+        return None;
+    }
+    let addr = u16::from_be_bytes([pdu[1], pdu[2]]);
+    let qty = u16::from_be_bytes([pdu[3], pdu[4]]);
+    let byte_count = pdu[5] as usize;
+    let data = &pdu[6..];
+    let expected = (qty as usize).div_ceil(8);
+    let unused_bits = expected * 8 - qty as usize;
+    let valid = byte_count == expected
+        && data.len() == byte_count
+        && (unused_bits == 0 || data[byte_count - 1] >> (8 - unused_bits) == 0);
+    Some(CoilPacking { addr, qty, valid })
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ConnectionStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.connection.is_closing() {
+            return Poll::Ready(Err(closed_by_server()));
+        }
+        if let Some(deadline) = &mut this.frame_deadline {
+            if deadline.as_mut().poll(cx).is_ready() {
+                this.frame_deadline = None;
+                this.metrics.record_read_timeout();
+                this.connection.close();
+                return Poll::Ready(Err(closed_by_server()));
+            }
+        }
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            if let Err(frame_start) = this.screen_protocol_ids(&mut buf.filled_mut()[filled..]) {
+                // Requests read ahead of the rejected one are still served; the codec never
+                // sees the rest, and the next read ends the connection.
+                buf.set_filled(filled + frame_start);
+                this.record_read(&buf.filled()[filled..]);
+                this.connection.close();
+                if frame_start == 0 {
+                    return Poll::Ready(Err(closed_by_server()));
+                }
+                return Poll::Ready(Ok(()));
+            }
+            this.record_read(&buf.filled()[filled..]);
+            this.track_frame();
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ConnectionStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let rewrite = this.response_unit_id.is_some() || this.protocol_ids == ProtocolIdMode::Echo;
+        let result = if rewrite {
+            let mut patched = buf.to_vec();
+            this.out_cursor.patch(&mut patched, this.response_unit_id, &this.protocols);
+            let result = Pin::new(&mut this.inner).poll_write(cx, &patched);
+            if let Poll::Ready(Ok(written)) = result {
+                this.out_cursor.advance(&patched[..written], &mut this.protocols);
+            }
+            result
+        } else {
+            Pin::new(&mut this.inner).poll_write(cx, buf)
+        };
+        if let Poll::Ready(Ok(written)) = result {
+            this.connection.traffic.record_out(written);
+            this.metrics.traffic.record_out(written);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use super::*;
+    use crate::connections::ConnectionRegistry;
+
+    /// A read request for unit 1 with transaction id `transaction` and protocol id `protocol`.
+    fn request(transaction: u16, protocol: u16) -> Vec<u8> {
+        let [t0, t1] = transaction.to_be_bytes();
+        let [p0, p1] = protocol.to_be_bytes();
+        vec![t0, t1, p0, p1, 0, 6, 1, 0x03, 0, 0, 0, 1]
+    }
+
+    fn stream(mode: ProtocolIdMode) -> (ConnectionStream<DuplexStream>, DuplexStream) {
+        timed_stream(mode, None)
+    }
+
+    fn timed_stream(
+        mode: ProtocolIdMode,
+        read_timeout: Option<Duration>,
+    ) -> (ConnectionStream<DuplexStream>, DuplexStream) {
+        let (server, client) = duplex(1024);
+        let peer = "127.0.0.1:1".parse().unwrap();
+        let connection = ConnectionRegistry::default().register(peer, None);
+        let metrics = Arc::new(ServerMetrics::default());
+        let stream = ConnectionStream::new(server, connection, metrics, None, mode, read_timeout);
+        (stream, client)
+    }
+
+    #[tokio::test]
+    async fn echo_clears_the_protocol_id_and_restores_it_in_the_response() {
+        let (mut stream, mut client) = stream(ProtocolIdMode::Echo);
+        client.write_all(&request(7, 0x1234)).await.unwrap();
+        let mut received = [0u8; 12];
+        stream.read_exact(&mut received).await.unwrap();
+        assert_eq!(received.to_vec(), request(7, 0));
+
+        stream
+            .write_all(&[0, 7, 0, 0, 0, 5, 1, 0x03, 2, 0, 42])
+            .await
+            .unwrap();
+        let mut response = [0u8; 11];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, [0, 7, 0x12, 0x34, 0, 5, 1, 0x03, 2, 0, 42]);
+    }
+
+    #[tokio::test]
+    async fn reject_keeps_earlier_requests_and_ends_the_stream() {
+        let (mut stream, mut client) = stream(ProtocolIdMode::Reject);
+        let mut frames = request(1, 0);
+        frames.extend(request(2, 5));
+        frames.extend(request(3, 0));
+        client.write_all(&frames).await.unwrap();
+
+        let mut received = [0u8; 36];
+        assert_eq!(stream.read(&mut received).await.unwrap(), 12);
+        assert_eq!(received[..12], request(1, 0));
+        let err = stream.read(&mut received).await.unwrap_err();
+        assert!(is_closed_by_server(&err));
+        assert!(stream.connection.is_closing());
+        assert_eq!(stream.metrics.traffic.bytes_in(), 12);
+    }
+
+    #[tokio::test]
+    async fn reject_of_a_first_request_ends_the_stream() {
+        let (mut stream, mut client) = stream(ProtocolIdMode::Reject);
+        client.write_all(&request(1, 1)).await.unwrap();
+        let mut received = [0u8; 12];
+        let err = stream.read(&mut received).await.unwrap_err();
+        assert!(is_closed_by_server(&err));
+        assert!(stream.connection.is_closing());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_partial_request_times_out() {
+        let timeout = Duration::from_secs(2);
+        let (mut stream, mut client) = timed_stream(ProtocolIdMode::Reject, Some(timeout));
+        client.write_all(&request(1, 0)[..9]).await.unwrap();
+        let mut received = [0u8; 12];
+        assert_eq!(stream.read(&mut received).await.unwrap(), 9);
+
+        let started = tokio::time::Instant::now();
+        let err = stream.read(&mut received).await.unwrap_err();
+        assert!(is_closed_by_server(&err));
+        assert!(started.elapsed() >= timeout);
+        assert!(stream.connection.is_closing());
+        assert_eq!(stream.metrics.snapshot().read_timeouts, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_time_between_requests_is_not_limited() {
+        let timeout = Duration::from_secs(2);
+        let (mut stream, mut client) = timed_stream(ProtocolIdMode::Reject, Some(timeout));
+        client.write_all(&request(1, 0)).await.unwrap();
+        let mut received = [0u8; 12];
+        stream.read_exact(&mut received).await.unwrap();
+
+        let idle = tokio::time::timeout(timeout * 5, stream.read(&mut received)).await;
+        assert!(idle.is_err());
+        assert!(!stream.connection.is_closing());
+        assert_eq!(stream.metrics.snapshot().read_timeouts, 0);
+    }
+}
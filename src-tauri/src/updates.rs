@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Notify;
+
+use crate::metrics::ServerMetrics;
+use crate::modbus::UpdatePayload;
+
+/// Bounded queue in front of `modbus://updated`. A single task drains it into the event system,
+/// so a slow UI can hold at most `capacity` pending updates; when full, the oldest one is
+/// discarded and counted in the metrics, telling the UI to resync from a store dump. A
+/// capacity of zero disables queueing and updates are emitted inline.
+pub struct UpdateQueue {
+    capacity: AtomicUsize,
+    pending: Mutex<VecDeque<UpdatePayload>>,
+    ready: Notify,
+    metrics: Arc<ServerMetrics>,
+}
+
+impl UpdateQueue {
+    pub fn new(metrics: Arc<ServerMetrics>) -> Self {
+        Self {
+            capacity: AtomicUsize::new(0),
+            pending: Mutex::default(),
+            ready: Notify::new(),
+            metrics,
+        }
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::SeqCst);
+    }
+
+    pub fn spawn_drain(self: &Arc<Self>, app: AppHandle) {
+        let queue = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                queue.ready.notified().await;
+                loop {
+                    let next = match queue.pending.lock() {
+                        Ok(mut pending) => pending.pop_front(),
+                        Err(_) => return,
+                    };
+                    let Some(payload) = next else {
+                        break;
+                    };
+                    let _ = app.emit("modbus://updated", payload);
+                }
+            }
+        });
+    }
+
+    pub fn emit(&self, app: &AppHandle, payload: UpdatePayload) {
+        let capacity = self.capacity.load(Ordering::SeqCst);
+        if capacity == 0 {
+            let _ = app.emit("modbus://updated", payload);
+            return;
+        }
+        {
+            let Ok(mut pending) = self.pending.lock() else {
+                return;
+            };
+            while pending.len() >= capacity {
+                pending.pop_front();
+                self.metrics.record_dropped_update();
+            }
+            pending.push_back(payload);
+        }
+        self.ready.notify_one();
+    }
+}
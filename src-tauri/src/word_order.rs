@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Byte order of a 32-bit value spread over two registers, named after the positions of
+/// bytes `A B C D` of the big-endian value.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WordOrder {
+    #[default]
+    Abcd,
+    Cdab,
+    Badc,
+    Dcba,
+}
+
+impl WordOrder {
+    pub fn decode_u32(self, first: u16, second: u16) -> u32 {
+        let [a, b] = first.to_be_bytes();
+        let [c, d] = second.to_be_bytes();
+        let bytes = match self {
+            WordOrder::Abcd => [a, b, c, d],
+            WordOrder::Cdab => [c, d, a, b],
+            WordOrder::Badc => [b, a, d, c],
+            WordOrder::Dcba => [d, c, b, a],
+        };
+        u32::from_be_bytes(bytes)
+    }
+
+    pub fn decode_i32(self, first: u16, second: u16) -> i32 {
+        self.decode_u32(first, second) as i32
+    }
+
+    pub fn decode_f32(self, first: u16, second: u16) -> f32 {
+        f32::from_bits(self.decode_u32(first, second))
+    }
+}
+
+/// Parses the serialized names case-insensitively, e.g. from a command line.
+impl FromStr for WordOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "abcd" => Ok(WordOrder::Abcd),
+            "cdab" => Ok(WordOrder::Cdab),
+            "badc" => Ok(WordOrder::Badc),
+            "dcba" => Ok(WordOrder::Dcba),
+            _ => Err(format!("Unknown word order: {value}")),
+        }
+    }
+}
+
+/// First address of a holding register pair holding an `f32`, given either bare or together
+/// with a word order that overrides the global one for this pair only.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum FloatPair {
+    Address(u16),
+    Ordered {
+        address: u16,
+        word_order: Option<WordOrder>,
+    },
+}
+
+impl FloatPair {
+    pub fn address(self) -> u16 {
+        match self {
+            FloatPair::Address(address) | FloatPair::Ordered { address, .. } => address,
+        }
+    }
+
+    pub fn word_order(self, default: WordOrder) -> WordOrder {
+        match self {
+            FloatPair::Ordered {
+                word_order: Some(order),
+                ..
+            } => order,
+            _ => default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_case_insensitively() {
+        assert_eq!("CDAB".parse::<WordOrder>(), Ok(WordOrder::Cdab));
+        assert_eq!("dcba".parse::<WordOrder>(), Ok(WordOrder::Dcba));
+        assert!("abdc".parse::<WordOrder>().is_err());
+    }
+
+    #[test]
+    fn float_pairs_take_a_bare_address_or_an_object() {
+        let pairs: Vec<FloatPair> = serde_json::from_value(serde_json::json!([
+            4,
+            { "address": 6, "word_order": "cdab" },
+            { "address": 8 },
+        ]))
+        .unwrap();
+        assert_eq!(pairs[0], FloatPair::Address(4));
+        let addresses: Vec<u16> = pairs.iter().map(|pair| pair.address()).collect();
+        assert_eq!(addresses, vec![4, 6, 8]);
+        let orders: Vec<WordOrder> = pairs
+            .iter()
+            .map(|pair| pair.word_order(WordOrder::Dcba))
+            .collect();
+        assert_eq!(
+            orders,
+            vec![WordOrder::Dcba, WordOrder::Cdab, WordOrder::Dcba]
+        );
+    }
+}
@@ -68,7 +68,7 @@ export const useModbusStore = defineStore("modbus", {
     },
     async startServer() {
       try {
-        this.status = (await invoke("server_start", {
+        this.status = (await invoke("server_ensure_started", {
           config: this.config,
         })) as ServerStatus;
       } catch (error) {
@@ -143,6 +143,10 @@ export const useModbusStore = defineStore("modbus", {
       void listen<UpdatePayload>("modbus://updated", (event) => {
         this.applyUpdate(event.payload);
       });
+      // Sent when the whole store is swapped; reload the visible page instead of patching it.
+      void listen("modbus://snapshot", () => {
+        void this.fetchSnapshot();
+      });
       void listen<ServerStatus>("modbus://status", (event) => {
         this.status = event.payload;
       });